@@ -0,0 +1,66 @@
+//! Plays a large generated level and logs frame timings, for comparing
+//! performance between changes.
+//!
+//! ```text
+//! cargo run --release --example stress -- [width] [height]
+//! ```
+//!
+//! The level defaults to 500 by 200 tiles. F3 additionally shows the game's
+//! own collider and physics counters.
+
+use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::prelude::*;
+use kyberchelik::level::LevelOverride;
+use kyberchelik::{GamePlugin, WINDOW_HEIGHT, WINDOW_WIDTH};
+
+const DEFAULT_WIDTH: usize = 500;
+const DEFAULT_HEIGHT: usize = 200;
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut size = |default: usize| {
+        args.next()
+            .and_then(|arg| arg.parse().ok())
+            .unwrap_or(default)
+            .max(8)
+    };
+    let (width, height) = (size(DEFAULT_WIDTH), size(DEFAULT_HEIGHT));
+
+    App::new()
+        .insert_resource(LevelOverride(generate_level(width, height)))
+        .add_plugins(DefaultPlugins.set(WindowPlugin {
+            primary_window: Some(Window {
+                title: "KyberCheliK stress test".to_string(),
+                resolution: (WINDOW_WIDTH, WINDOW_HEIGHT).into(),
+                resizable: false,
+                ..default()
+            }),
+            ..default()
+        }))
+        .add_plugins((FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin::default()))
+        .add_systems(Startup, move || info!("generated a {width}x{height} level"))
+        .add_plugins(GamePlugin)
+        .run();
+}
+
+/// A walled box of staggered ledges: every fourth row is a run of platforms
+/// with gaps, so there are many separate colliders rather than a few long ones.
+fn generate_level(width: usize, height: usize) -> Vec<String> {
+    (0..height)
+        .map(|row| {
+            (0..width)
+                .map(|col| {
+                    let border = row == 0 || row + 1 == height || col == 0 || col + 1 == width;
+                    let ledge = row % 4 == 0 && (col + row * 3) % 16 < 10;
+                    if row + 2 == height && col == 1 {
+                        'P'
+                    } else if border || ledge {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,859 @@
+//! The level map: parsing and validating it, spawning its tiles and restarting it.
+
+use bevy::prelude::*;
+use bevy::sprite::SpriteBundle;
+use bevy::utils::HashMap;
+use bevy_xpbd_2d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+#[cfg(feature = "hot_reload")]
+use crate::player::{Player, SpawnOffset, WatchedFile};
+use crate::{
+    z_layers, GameplaySet, BACKGROUND_SRGB, DEFAULT_TILE_SIZE, PLAYER_SPAWN, SOLID_LAYERS,
+    TILE_SRGB,
+};
+
+pub(crate) mod checkpoints;
+pub(crate) mod crumble;
+pub(crate) mod keys;
+
+use checkpoints::spawn_checkpoint;
+use crumble::spawn_crumble_tile;
+use keys::{spawn_door, spawn_key};
+
+/// Loads the level map, spawns it and restarts it on demand.
+pub struct LevelPlugin;
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LevelRestarted>()
+            .add_event::<LevelReloaded>()
+            .add_systems(Startup, setup_level)
+            .add_systems(
+                Update,
+                (quick_restart, animate_tile_spawn).in_set(GameplaySet),
+            );
+        #[cfg(feature = "hot_reload")]
+        app.insert_resource(LevelFile(WatchedFile::new(level_path())))
+            .add_systems(Update, reload_level_file);
+    }
+}
+
+/// Sprite of one solid `'#'` cell. Collision lives on [`TileCollider`]s instead.
+#[derive(Component)]
+pub struct LevelTile;
+
+/// Static body covering a horizontal run of solid cells, merged at spawn so a big
+/// level has one collider per run instead of one per tile.
+#[derive(Component)]
+pub struct TileCollider;
+
+/// Grows a tile's sprite in when the level loads. The timer covers the tile's
+/// stagger delay followed by `TILE_GROW_TIME`.
+///
+/// Only the sprite is animated: tile colliders are separate entities and are
+/// solid from the first frame.
+#[derive(Component, Deref, DerefMut)]
+pub(crate) struct TileSpawnAnim(Timer);
+
+const TILE_GROW_TIME: f32 = 0.25;
+/// Extra delay per grid step from the top-left corner, for a diagonal sweep.
+const TILE_SWEEP_DELAY: f32 = 0.015;
+
+/// Anything spawned from the level map, despawned when the level restarts.
+#[derive(Component)]
+pub struct LevelEntity;
+
+/// Where the player appears when the level starts or restarts.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct SpawnPoint(pub Vec2);
+
+impl Default for SpawnPoint {
+    fn default() -> Self {
+        Self(PLAYER_SPAWN)
+    }
+}
+
+/// Fired after the current level has been torn down and spawned again.
+#[derive(Event)]
+pub struct LevelRestarted;
+
+/// Sent when the level file changed on disk and the level was respawned from it.
+#[derive(Event)]
+pub struct LevelReloaded;
+
+const LEVEL_MAP: [&str; 11] = [
+    "####################",
+    "#..................#",
+    "#.................##",
+    "#..................#",
+    "#...............#..#",
+    "#...###........##..#",
+    "#...............D..#",
+    "#.........###...D..#",
+    "#...............D..#",
+    "#P.k............D..#",
+    "####################",
+];
+
+/// Spawned instead of a map that fails validation.
+const FALLBACK_LEVEL: [&str; 5] = [
+    "####################",
+    "#..................#",
+    "#..................#",
+    "#P.................#",
+    "####################",
+];
+
+/// Map rows to play instead of the level file, for tools and examples that build
+/// their own level. Insert it before startup; it goes through the same
+/// validation as a map read from disk.
+#[derive(Resource, Clone, Debug)]
+pub struct LevelOverride(pub Vec<String>);
+
+/// Scale and placement of the level currently loaded.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct LevelConfig {
+    /// Side of one grid cell in pixels; `tile_size` in `level.ron` sets it.
+    pub tile_size: f32,
+    /// World Y of the centres of the top row; the rest of the map hangs below it.
+    pub top_row_y: f32,
+}
+
+impl Default for LevelConfig {
+    fn default() -> Self {
+        Self {
+            tile_size: DEFAULT_TILE_SIZE,
+            top_row_y: -160.0,
+        }
+    }
+}
+
+impl LevelConfig {
+    /// The config for a level with `theme`, logging and ignoring a tile size
+    /// that can't be used.
+    pub(crate) fn for_theme(theme: &LevelTheme) -> Self {
+        let mut config = Self::default();
+        match theme.tile_size {
+            Some(size) if size.is_finite() && size >= 1.0 => config.tile_size = size,
+            Some(size) => warn!(
+                "tile_size {size} in the level theme is not usable, keeping {}",
+                config.tile_size
+            ),
+            None => {}
+        }
+        config
+    }
+}
+
+/// Characters a level map may contain.
+const LEVEL_TILES: [char; 7] = ['#', '.', 'k', 'D', 'P', 'T', 'C'];
+
+/// A validated level map: every row has the same width and only known tiles.
+#[derive(Resource, Clone, Debug)]
+pub struct LevelData {
+    pub(crate) rows: Vec<String>,
+    pub(crate) width: usize,
+    /// Grid cell (column, row) of the `'P'` marker, if the map has one.
+    pub(crate) spawn: Option<(usize, usize)>,
+    pub(crate) theme: LevelTheme,
+}
+
+/// Look and scale of a level, read from `level.ron` next to the level file.
+/// Colours are sRGB triples; anything left out keeps the default.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub(crate) struct LevelTheme {
+    background: [f32; 3],
+    tile: [f32; 3],
+    /// Track to play in this level. Recorded for when the game gets audio; nothing
+    /// plays it yet.
+    music: Option<String>,
+    /// Side of one grid cell in pixels, for levels built denser or coarser than
+    /// `DEFAULT_TILE_SIZE`.
+    tile_size: Option<f32>,
+}
+
+impl Default for LevelTheme {
+    fn default() -> Self {
+        Self {
+            background: BACKGROUND_SRGB,
+            tile: TILE_SRGB,
+            music: None,
+            tile_size: None,
+        }
+    }
+}
+
+impl LevelTheme {
+    pub(crate) fn background_color(&self) -> Color {
+        let [r, g, b] = self.background;
+        Color::srgb(r, g, b)
+    }
+
+    pub(crate) fn tile_color(&self) -> Color {
+        let [r, g, b] = self.tile;
+        Color::srgb(r, g, b)
+    }
+}
+
+fn theme_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("kyberchelik").join("level.ron"))
+}
+
+fn load_theme() -> LevelTheme {
+    let Some(contents) = theme_path().and_then(|path| fs::read_to_string(path).ok()) else {
+        return LevelTheme::default();
+    };
+    ron::from_str(&contents).unwrap_or_else(|err| {
+        warn!("level theme is invalid: {err}, using the default look");
+        LevelTheme::default()
+    })
+}
+
+impl LevelData {
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// World position of the top-left corner of the level grid.
+    pub fn origin(&self, config: &LevelConfig) -> Vec2 {
+        Vec2::new(
+            -config.tile_size * self.width as f32 * 0.5,
+            config.top_row_y,
+        )
+    }
+
+    /// World-space rectangle covered by the level grid.
+    pub fn bounds(&self, config: &LevelConfig) -> Rect {
+        let tile_size = config.tile_size;
+        let origin = self.origin(config);
+        Rect::new(
+            origin.x,
+            origin.y - (self.height() as f32 - 0.5) * tile_size,
+            origin.x + self.width as f32 * tile_size,
+            origin.y + tile_size * 0.5,
+        )
+    }
+
+    /// World position of the center of a grid cell.
+    pub fn tile_center(&self, col: usize, row: usize, config: &LevelConfig) -> Vec2 {
+        let tile_size = config.tile_size;
+        self.origin(config)
+            + Vec2::new(
+                col as f32 * tile_size + tile_size * 0.5,
+                -(row as f32) * tile_size,
+            )
+    }
+
+    /// Door id of every `'D'` cell. Cells next to or on top of each other share
+    /// an id, so a doorway several tiles tall is one door.
+    pub(crate) fn door_groups(&self) -> HashMap<(usize, usize), u32> {
+        let grid: Vec<Vec<char>> = self.rows.iter().map(|row| row.chars().collect()).collect();
+        let is_door =
+            |col: usize, row: usize| grid.get(row).and_then(|line| line.get(col)) == Some(&'D');
+
+        let mut groups = HashMap::new();
+        let mut next = 0;
+        for row in 0..grid.len() {
+            for col in 0..self.width {
+                if !is_door(col, row) || groups.contains_key(&(col, row)) {
+                    continue;
+                }
+                groups.insert((col, row), next);
+                let mut stack = vec![(col, row)];
+                while let Some((col, row)) = stack.pop() {
+                    let neighbours = [
+                        (col.wrapping_sub(1), row),
+                        (col + 1, row),
+                        (col, row.wrapping_sub(1)),
+                        (col, row + 1),
+                    ];
+                    for (col, row) in neighbours {
+                        if is_door(col, row) && groups.insert((col, row), next).is_none() {
+                            stack.push((col, row));
+                        }
+                    }
+                }
+                next += 1;
+            }
+        }
+        groups
+    }
+
+    /// Whether a cell is solid when the level starts: a wall, a closed door or a
+    /// crumbling tile. Cells outside the map count as solid.
+    pub(crate) fn is_solid(&self, col: usize, row: usize) -> bool {
+        self.rows
+            .get(row)
+            .and_then(|line| line.chars().nth(col))
+            .is_none_or(|tile| matches!(tile, '#' | 'D' | 'T'))
+    }
+
+    /// Grid cell (column, row) containing a world position, clamped to the map.
+    pub fn world_to_grid(&self, position: Vec2, config: &LevelConfig) -> UVec2 {
+        let tile_size = config.tile_size;
+        let local = position - self.origin(config);
+        let col = (local.x / tile_size).floor();
+        let row = (-local.y / tile_size).round();
+        UVec2::new(
+            col.clamp(0.0, (self.width - 1) as f32) as u32,
+            row.clamp(0.0, (self.height() - 1) as f32) as u32,
+        )
+    }
+}
+
+#[derive(Debug)]
+enum LevelError {
+    Empty,
+    RaggedRow {
+        row: usize,
+        expected: usize,
+        found: usize,
+    },
+    UnknownTile {
+        row: usize,
+        col: usize,
+        tile: char,
+    },
+    MultipleSpawns {
+        first: (usize, usize),
+        second: (usize, usize),
+    },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Empty => write!(f, "level has no rows"),
+            LevelError::RaggedRow {
+                row,
+                expected,
+                found,
+            } => write!(
+                f,
+                "row {row} is {found} tiles wide, expected {expected} like the first row"
+            ),
+            LevelError::UnknownTile { row, col, tile } => {
+                write!(f, "unknown tile {tile:?} at row {row}, column {col}")
+            }
+            LevelError::MultipleSpawns { first, second } => write!(
+                f,
+                "more than one spawn marker 'P': row {}, column {} and row {}, column {}",
+                first.0, first.1, second.0, second.1
+            ),
+        }
+    }
+}
+
+/// Checks that a map is rectangular, uses only known tiles and has at most one spawn.
+///
+/// A map without a spawn marker is accepted; the player then uses `PLAYER_SPAWN`.
+fn validate_level(map: &[String]) -> Result<LevelData, LevelError> {
+    let width = map.first().ok_or(LevelError::Empty)?.chars().count();
+    if width == 0 {
+        return Err(LevelError::Empty);
+    }
+
+    let mut spawn = None;
+    for (row, line) in map.iter().enumerate() {
+        let found = line.chars().count();
+        if found != width {
+            return Err(LevelError::RaggedRow {
+                row,
+                expected: width,
+                found,
+            });
+        }
+
+        for (col, tile) in line.chars().enumerate() {
+            if !LEVEL_TILES.contains(&tile) {
+                return Err(LevelError::UnknownTile { row, col, tile });
+            }
+            if tile == 'P' {
+                if let Some((first_col, first_row)) = spawn {
+                    return Err(LevelError::MultipleSpawns {
+                        first: (first_row, first_col),
+                        second: (row, col),
+                    });
+                }
+                spawn = Some((col, row));
+            }
+        }
+    }
+
+    Ok(LevelData {
+        rows: map.to_vec(),
+        width,
+        spawn,
+        theme: LevelTheme::default(),
+    })
+}
+
+/// Validates `map`, falling back to [`FALLBACK_LEVEL`] with a logged error if it is broken.
+pub(crate) fn load_level(map: &[String]) -> LevelData {
+    validate_level(map).unwrap_or_else(|err| {
+        error!("invalid level: {err}; loading the fallback level instead");
+        let fallback: Vec<String> = FALLBACK_LEVEL.iter().map(|row| row.to_string()).collect();
+        validate_level(&fallback).expect("fallback level is valid")
+    })
+}
+
+/// Text file the level is read from when present; the editor exports to it too.
+pub(crate) fn level_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("kyberchelik").join("level.txt"))
+}
+
+fn parse_level_text(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(|line| line.trim_end().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+pub(crate) fn setup_level(mut commands: Commands, level_override: Option<Res<LevelOverride>>) {
+    let map = match level_override {
+        Some(level_override) => level_override.0.clone(),
+        None => level_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|contents| parse_level_text(&contents))
+            .unwrap_or_else(|| LEVEL_MAP.iter().map(|row| row.to_string()).collect()),
+    };
+    let mut level = load_level(&map);
+    level.theme = load_theme();
+    let config = LevelConfig::for_theme(&level.theme);
+    spawn_level(&mut commands, &level, &config, true);
+    commands.insert_resource(level);
+    commands.insert_resource(config);
+}
+
+/// Spawns every entity of `level`. `animate` plays the tile spawn-in sweep; quick
+/// restarts skip it so practice isn't slowed down.
+pub(crate) fn spawn_level(
+    commands: &mut Commands,
+    level: &LevelData,
+    config: &LevelConfig,
+    animate: bool,
+) {
+    let tile_size = config.tile_size;
+    commands.insert_resource(ClearColor(level.theme.background_color()));
+    spawn_tile_colliders(commands, level, config);
+
+    let spawn = level.spawn.map_or(PLAYER_SPAWN, |(col, row)| {
+        level.tile_center(col, row, config)
+    });
+    commands.insert_resource(SpawnPoint(spawn));
+
+    let doors = level.door_groups();
+    for (row, line) in level.rows.iter().enumerate() {
+        for (col, ch) in line.chars().enumerate() {
+            let position = level.tile_center(col, row, config);
+
+            match ch {
+                '#' => {
+                    let anim = animate.then(|| {
+                        let delay = (row + col) as f32 * TILE_SWEEP_DELAY;
+                        TileSpawnAnim(Timer::from_seconds(delay + TILE_GROW_TIME, TimerMode::Once))
+                    });
+                    spawn_tile(
+                        commands,
+                        position,
+                        tile_size,
+                        level.theme.tile_color(),
+                        anim,
+                    );
+                }
+                'k' => spawn_key(commands, position, tile_size),
+                'D' => spawn_door(commands, position, tile_size, doors[&(col, row)]),
+                'T' => spawn_crumble_tile(commands, position, tile_size),
+                'C' => spawn_checkpoint(commands, position, tile_size),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Spawns the sprite of one solid tile; with `anim` it starts at zero size and grows in.
+pub(crate) fn spawn_tile(
+    commands: &mut Commands,
+    position: Vec2,
+    tile_size: f32,
+    color: Color,
+    anim: Option<TileSpawnAnim>,
+) {
+    let size = if anim.is_some() {
+        Vec2::ZERO
+    } else {
+        Vec2::splat(tile_size)
+    };
+
+    let mut tile = commands.spawn((
+        LevelTile,
+        LevelEntity,
+        SpriteBundle {
+            sprite: Sprite {
+                color,
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, z_layers::TILES),
+            ..default()
+        },
+    ));
+    if let Some(anim) = anim {
+        tile.insert(anim);
+    }
+}
+
+/// Spawns one [`TileCollider`] per maximal horizontal run of `'#'` in each row.
+fn spawn_tile_colliders(commands: &mut Commands, level: &LevelData, config: &LevelConfig) {
+    let tile_size = config.tile_size;
+    for (row, line) in level.rows.iter().enumerate() {
+        let cells: Vec<char> = line.chars().collect();
+        let mut col = 0;
+        while col < cells.len() {
+            if cells[col] != '#' {
+                col += 1;
+                continue;
+            }
+
+            let start = col;
+            while col < cells.len() && cells[col] == '#' {
+                col += 1;
+            }
+            let end = col - 1;
+
+            let center =
+                (level.tile_center(start, row, config) + level.tile_center(end, row, config)) * 0.5;
+            commands.spawn((
+                TileCollider,
+                LevelEntity,
+                TransformBundle::from_transform(Transform::from_xyz(
+                    center.x,
+                    center.y,
+                    z_layers::TILES,
+                )),
+                RigidBody::Static,
+                Collider::rectangle((end - start + 1) as f32 * tile_size, tile_size),
+                SOLID_LAYERS,
+            ));
+        }
+    }
+}
+
+pub(crate) fn quick_restart(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    level: Res<LevelData>,
+    config: Res<LevelConfig>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+    mut restarted: EventWriter<LevelRestarted>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyR) {
+        return;
+    }
+
+    for entity in &level_entities {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_level(&mut commands, &level, &config, false);
+    restarted.send(LevelRestarted);
+}
+
+#[cfg(feature = "hot_reload")]
+#[derive(Resource)]
+struct LevelFile(WatchedFile);
+
+/// Respawns the level when its file changes. Players keep their position unless
+/// it is now inside a solid tile, in which case they go back to the spawn. A file
+/// that fails validation is logged and the current level kept.
+#[cfg(feature = "hot_reload")]
+fn reload_level_file(
+    mut commands: Commands,
+    time: Res<Time<Real>>,
+    mut file: ResMut<LevelFile>,
+    mut level: ResMut<LevelData>,
+    mut config: ResMut<LevelConfig>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+    mut players: Query<(&mut Transform, &mut LinearVelocity, &SpawnOffset), With<Player>>,
+    mut reloaded: EventWriter<LevelReloaded>,
+) {
+    if !file.0.changed(time.delta()) {
+        return;
+    }
+    let Some(contents) = file
+        .0
+        .path
+        .as_ref()
+        .and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return;
+    };
+    let mut new_level = match validate_level(&parse_level_text(&contents)) {
+        Ok(new_level) => new_level,
+        Err(err) => {
+            error!("level file is invalid: {err}; keeping the current level");
+            return;
+        }
+    };
+    new_level.theme = load_theme();
+    *config = LevelConfig::for_theme(&new_level.theme);
+
+    for entity in &level_entities {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_level(&mut commands, &new_level, &config, false);
+    let spawn = new_level.spawn.map_or(PLAYER_SPAWN, |(col, row)| {
+        new_level.tile_center(col, row, &config)
+    });
+    for (mut transform, mut velocity, offset) in &mut players {
+        let cell = new_level.world_to_grid(transform.translation.truncate(), &config);
+        let inside_wall = new_level.rows[cell.y as usize].chars().nth(cell.x as usize) == Some('#');
+        if inside_wall {
+            let position = spawn + offset.0;
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            velocity.0 = Vec2::ZERO;
+        }
+    }
+    *level = new_level;
+    info!("reloaded level file");
+    reloaded.send(LevelReloaded);
+}
+
+fn animate_tile_spawn(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<LevelConfig>,
+    mut tiles: Query<(Entity, &mut TileSpawnAnim, &mut Sprite)>,
+) {
+    for (entity, mut anim, mut sprite) in &mut tiles {
+        anim.tick(time.delta());
+
+        let remaining = anim.duration().as_secs_f32() - anim.elapsed_secs();
+        let t = (1.0 - remaining / TILE_GROW_TIME).clamp(0.0, 1.0);
+        // Ease out so tiles settle into place rather than stopping abruptly.
+        let eased = 1.0 - (1.0 - t).powi(3);
+        sprite.custom_size = Some(Vec2::splat(config.tile_size * eased));
+
+        if anim.finished() {
+            commands.entity(entity).remove::<TileSpawnAnim>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::{Grounded, Player};
+    use crate::testing::{step, test_app};
+
+    fn level(map: &[&str]) -> LevelData {
+        validate_level(&rows(map)).unwrap()
+    }
+
+    fn rows(map: &[&str]) -> Vec<String> {
+        map.iter().map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn validation_reports_what_is_wrong_and_where() {
+        assert!(matches!(validate_level(&[]), Err(LevelError::Empty)));
+        assert!(matches!(
+            validate_level(&rows(&["", ""])),
+            Err(LevelError::Empty)
+        ));
+        assert!(matches!(
+            validate_level(&rows(&["####", "#P#", "####"])),
+            Err(LevelError::RaggedRow {
+                row: 1,
+                expected: 4,
+                found: 3
+            })
+        ));
+        assert!(matches!(
+            validate_level(&rows(&["####", "#P?#", "####"])),
+            Err(LevelError::UnknownTile {
+                row: 1,
+                col: 2,
+                tile: '?'
+            })
+        ));
+        assert!(matches!(
+            validate_level(&rows(&["#P.#", "#.P#"])),
+            Err(LevelError::MultipleSpawns {
+                first: (0, 1),
+                second: (1, 2)
+            })
+        ));
+    }
+
+    #[test]
+    fn a_valid_map_keeps_its_spawn_and_a_broken_one_falls_back() {
+        let valid = validate_level(&rows(&["####", "#.P#", "####"])).unwrap();
+        assert_eq!((valid.width, valid.spawn), (4, Some((2, 1))));
+
+        let fallback = load_level(&rows(&["##", "#"]));
+        assert_eq!(fallback.rows, rows(&FALLBACK_LEVEL));
+    }
+
+    #[test]
+    fn tile_size_comes_from_the_level_theme() {
+        let theme: LevelTheme = ron::from_str("(tile_size: Some(24.0))").unwrap();
+        let config = LevelConfig::for_theme(&theme);
+        assert_eq!(config.tile_size, 24.0);
+
+        let level = level(&["###", "#P#"]);
+        let center = level.tile_center(1, 1, &config);
+        assert_eq!(center, Vec2::new(0.0, config.top_row_y - 24.0));
+    }
+
+    #[test]
+    fn unusable_tile_size_falls_back_to_the_default() {
+        for size in ["0.0", "-8.0", "inf"] {
+            let theme: LevelTheme = ron::from_str(&format!("(tile_size: Some({size}))")).unwrap();
+            assert_eq!(LevelConfig::for_theme(&theme).tile_size, DEFAULT_TILE_SIZE);
+        }
+    }
+
+    #[test]
+    fn touching_door_cells_form_one_door() {
+        let doors = level(&LEVEL_MAP).door_groups();
+        assert_eq!(doors.len(), 4);
+        assert!(doors.values().all(|&group| group == 0));
+    }
+
+    #[test]
+    fn separate_doors_get_their_own_ids() {
+        let doors = level(&["DD.D", "...D", "D..."]).door_groups();
+        assert_eq!(doors[&(0, 0)], doors[&(1, 0)]);
+        assert_eq!(doors[&(3, 0)], doors[&(3, 1)]);
+        assert_ne!(doors[&(0, 0)], doors[&(3, 0)]);
+        assert_ne!(doors[&(0, 2)], doors[&(0, 0)]);
+        assert_ne!(doors[&(0, 2)], doors[&(3, 0)]);
+    }
+
+    /// A shelf with one-tile gaps at columns 3 and 6 over a lower floor.
+    const GAPPED_SHELF: &[&str] = &[
+        "##########",
+        "#........#",
+        "#........#",
+        "#P.......#",
+        "###.##.###",
+        "#........#",
+        "##########",
+    ];
+
+    #[test]
+    fn merged_colliders_cover_exactly_the_solid_cells() {
+        let mut app = test_app(GAPPED_SHELF);
+        step(&mut app, 1);
+
+        let world = app.world_mut();
+        let level = world.resource::<LevelData>().clone();
+        let config = *world.resource::<LevelConfig>();
+        let colliders: Vec<ColliderAabb> = world
+            .query_filtered::<&ColliderAabb, With<TileCollider>>()
+            .iter(world)
+            .copied()
+            .collect();
+        // Rows 0 and 6 merge into one body, the shelf into three, the walls into
+        // one per row.
+        assert_eq!(colliders.len(), 2 + 3 + 2 * 4);
+
+        for (row, line) in level.rows.iter().enumerate() {
+            for (col, tile) in line.chars().enumerate() {
+                let center = level.tile_center(col, row, &config);
+                let covering = colliders
+                    .iter()
+                    .filter(|aabb| Rect::from_corners(aabb.min, aabb.max).contains(center))
+                    .count();
+                assert_eq!(covering, usize::from(tile == '#'), "cell {col}, {row}");
+            }
+        }
+    }
+
+    #[test]
+    fn merged_shelf_grounds_like_single_tiles() {
+        for (col, solid) in [(2, true), (3, false), (4, true), (5, true), (6, false)] {
+            let mut app = test_app(GAPPED_SHELF);
+            step(&mut app, 1);
+
+            let world = app.world_mut();
+            let level = world.resource::<LevelData>().clone();
+            let config = *world.resource::<LevelConfig>();
+            let drop = level.tile_center(col, 2, &config);
+            let shelf_top = level.tile_center(col, 4, &config).y + config.tile_size * 0.5;
+            world
+                .query_filtered::<&mut Position, With<Player>>()
+                .single_mut(world)
+                .0 = drop;
+            step(&mut app, 60);
+
+            let world = app.world_mut();
+            let (position, grounded) = world
+                .query_filtered::<(&Position, &Grounded), With<Player>>()
+                .single(world);
+            assert!(grounded.0, "column {col}");
+            assert_eq!(position.y > shelf_top, solid, "column {col}");
+        }
+    }
+
+    /// Staggered ledges with gaps, the kind of map the stress example plays.
+    const LEDGE_FIELD: &[&str] = &[
+        "########################",
+        "#......................#",
+        "#..######....######....#",
+        "#......................#",
+        "#......######....####..#",
+        "#......................#",
+        "#P.....................#",
+        "########################",
+    ];
+
+    #[test]
+    fn an_override_is_played_instead_of_the_level_file() {
+        let mut app = test_app(LEDGE_FIELD);
+        step(&mut app, 60);
+
+        let world = app.world_mut();
+        let level = world.resource::<LevelData>().clone();
+        let config = *world.resource::<LevelConfig>();
+        assert_eq!(level.rows, rows(LEDGE_FIELD));
+        let spawn = level.tile_center(1, 6, &config);
+        assert_eq!(world.resource::<SpawnPoint>().0, spawn);
+
+        let (position, grounded) = world
+            .query_filtered::<(&Position, &Grounded), With<Player>>()
+            .single(world);
+        assert!(grounded.0);
+        assert!(
+            position.distance(spawn) < config.tile_size,
+            "at {}",
+            position.0
+        );
+    }
+
+    #[test]
+    fn an_invalid_override_falls_back_like_a_level_file() {
+        let mut app = test_app(&["#####", "#P#", "#####"]);
+        step(&mut app, 1);
+
+        assert_eq!(
+            app.world().resource::<LevelData>().rows,
+            rows(&FALLBACK_LEVEL)
+        );
+        let world = app.world_mut();
+        assert_eq!(
+            world
+                .query_filtered::<(), With<Player>>()
+                .iter(world)
+                .count(),
+            1
+        );
+    }
+}
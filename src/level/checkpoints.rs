@@ -0,0 +1,187 @@
+//! Map `'C'` checkpoints and the C key that warps back to the last one.
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use super::{quick_restart, LevelEntity, LevelReloaded, LevelRestarted, SpawnPoint};
+use crate::player::{Player, WarpPlayers};
+use crate::{z_layers, GameplaySet, COLLECTIBLE_LAYERS};
+
+const CHECKPOINT_COLOR: Color = Color::srgb(0.35, 0.45, 0.6);
+const CHECKPOINT_ACTIVE_COLOR: Color = Color::srgb(0.4, 1.0, 0.6);
+
+pub(crate) struct CheckpointPlugin;
+
+impl Plugin for CheckpointPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CheckpointConfig>()
+            .init_resource::<RespawnPoint>()
+            .add_event::<CheckpointReached>()
+            .add_systems(
+                Update,
+                (
+                    reset_checkpoints.after(quick_restart),
+                    activate_checkpoints,
+                    return_to_checkpoint,
+                )
+                    .chain()
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+#[derive(Resource, Default)]
+struct CheckpointConfig {
+    /// Touching a checkpoint that was already reached moves the respawn back to
+    /// it.
+    allow_backtrack: bool,
+}
+
+/// Map `'C'`: touching it makes it the point C warps back to. `visited` is set
+/// once it has been reached, so new checkpoints always count as progress in
+/// whichever direction the level runs.
+#[derive(Component, Default)]
+struct Checkpoint {
+    visited: bool,
+}
+
+/// The checkpoint activated last in the current level, if any.
+#[derive(Resource, Default)]
+struct RespawnPoint(Option<ActiveCheckpoint>);
+
+#[derive(Clone, Copy)]
+struct ActiveCheckpoint {
+    entity: Entity,
+    position: Vec2,
+}
+
+#[derive(Event)]
+struct CheckpointReached;
+
+pub(super) fn spawn_checkpoint(commands: &mut Commands, position: Vec2, tile_size: f32) {
+    commands.spawn((
+        Checkpoint::default(),
+        LevelEntity,
+        SpriteBundle {
+            sprite: Sprite {
+                color: CHECKPOINT_COLOR,
+                custom_size: Some(Vec2::new(tile_size * 0.2, tile_size * 0.8)),
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, z_layers::ITEMS),
+            ..default()
+        },
+        RigidBody::Static,
+        Collider::rectangle(tile_size * 0.2, tile_size * 0.8),
+        Sensor,
+        COLLECTIBLE_LAYERS,
+    ));
+}
+
+/// Respawned levels bring fresh, inactive checkpoints.
+fn reset_checkpoints(
+    mut restarted: EventReader<LevelRestarted>,
+    mut reloaded: EventReader<LevelReloaded>,
+    mut respawn: ResMut<RespawnPoint>,
+) {
+    let restarted = restarted.read().last().is_some();
+    if reloaded.read().last().is_some() || restarted {
+        respawn.0 = None;
+    }
+}
+
+fn activate_checkpoints(
+    config: Res<CheckpointConfig>,
+    mut respawn: ResMut<RespawnPoint>,
+    players: Query<&CollidingEntities, With<Player>>,
+    mut checkpoints: Query<(&mut Checkpoint, &Transform, &mut Sprite)>,
+    mut reached: EventWriter<CheckpointReached>,
+) {
+    for collisions in &players {
+        for &entity in collisions.iter() {
+            let Ok((checkpoint, transform, _)) = checkpoints.get(entity) else {
+                continue;
+            };
+            if respawn.0.is_some_and(|active| active.entity == entity)
+                || (checkpoint.visited && !config.allow_backtrack)
+            {
+                continue;
+            }
+
+            let active = ActiveCheckpoint {
+                entity,
+                position: transform.translation.truncate(),
+            };
+            if let Some(previous) = respawn.0.replace(active) {
+                if let Ok((_, _, mut sprite)) = checkpoints.get_mut(previous.entity) {
+                    sprite.color = CHECKPOINT_COLOR;
+                }
+            }
+            if let Ok((mut checkpoint, _, mut sprite)) = checkpoints.get_mut(entity) {
+                checkpoint.visited = true;
+                sprite.color = CHECKPOINT_ACTIVE_COLOR;
+            }
+            reached.send(CheckpointReached);
+        }
+    }
+}
+
+/// C warps the players back to the active checkpoint, or to the level spawn if
+/// none has been touched yet. The level itself is left as it is.
+fn return_to_checkpoint(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    respawn: Res<RespawnPoint>,
+    spawn: Res<SpawnPoint>,
+    mut warps: EventWriter<WarpPlayers>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyC) {
+        let target = respawn.0.map_or(spawn.0, |active| active.position);
+        warps.send(WarpPlayers(target));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{step, test_app};
+
+    /// Moves the player onto `target` and lets the checkpoints react.
+    fn touch(app: &mut App, target: Vec2) -> Option<Vec2> {
+        let world = app.world_mut();
+        world
+            .query_filtered::<&mut Position, With<Player>>()
+            .single_mut(world)
+            .0 = target;
+        step(app, 3);
+        app.world()
+            .resource::<RespawnPoint>()
+            .0
+            .map(|active| active.position)
+    }
+
+    #[test]
+    fn checkpoints_in_one_column_activate_in_the_order_reached() {
+        let mut app = test_app(&["#####", "#.C.#", "#...#", "#.C.#", "#P..#", "#####"]);
+        app.add_plugins(CheckpointPlugin);
+        step(&mut app, 30);
+
+        let world = app.world_mut();
+        let mut checkpoints: Vec<Vec2> = world
+            .query_filtered::<&Transform, With<Checkpoint>>()
+            .iter(world)
+            .map(|transform| transform.translation.truncate())
+            .collect();
+        checkpoints.sort_by(|a, b| a.y.total_cmp(&b.y));
+        let (lower, upper) = (checkpoints[0], checkpoints[1]);
+
+        assert_eq!(touch(&mut app, lower), Some(lower));
+        assert_eq!(touch(&mut app, upper), Some(upper));
+        // Already reached, so it only takes the respawn back with backtracking on.
+        assert_eq!(touch(&mut app, lower), Some(upper));
+
+        app.insert_resource(CheckpointConfig {
+            allow_backtrack: true,
+        });
+        assert_eq!(touch(&mut app, lower), Some(lower));
+    }
+}
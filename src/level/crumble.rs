@@ -0,0 +1,165 @@
+//! Map `'T'` tiles that give way under the player and come back later.
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use super::{LevelConfig, LevelEntity, TileCollider};
+use crate::player::{update_player_state, GravityFlip, Player};
+use crate::{z_layers, GameLayer, GameplaySet, GROUND_TOLERANCE, PLAYER_HALF_HEIGHT, SOLID_LAYERS};
+
+pub(crate) struct CrumblePlugin;
+
+impl Plugin for CrumblePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CrumbleConfig>().add_systems(
+            Update,
+            (start_crumbling, update_crumble_tiles)
+                .chain()
+                .after(update_player_state)
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+#[derive(Resource)]
+struct CrumbleConfig {
+    /// How long a tile holds after a player lands on it, in seconds.
+    crumble_time: f32,
+    /// How long a crumbled tile stays gone before it comes back, in seconds.
+    respawn_time: f32,
+}
+
+impl Default for CrumbleConfig {
+    fn default() -> Self {
+        Self {
+            crumble_time: 0.5,
+            respawn_time: 2.0,
+        }
+    }
+}
+
+/// Map `'T'`: a solid tile that gives way shortly after a player stands on it
+/// and comes back after a while.
+#[derive(Component)]
+struct CrumbleTile {
+    state: CrumbleState,
+    /// Counts down the current state; unused while solid.
+    timer: Timer,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum CrumbleState {
+    Solid,
+    Crumbling,
+    Gone,
+}
+
+const CRUMBLE_TILE_COLOR: Color = Color::srgb(0.42, 0.36, 0.30);
+
+pub(super) fn spawn_crumble_tile(commands: &mut Commands, position: Vec2, tile_size: f32) {
+    commands.spawn((
+        CrumbleTile {
+            state: CrumbleState::Solid,
+            timer: Timer::default(),
+        },
+        // Counts as floor and walls for grounding like any other tile.
+        TileCollider,
+        LevelEntity,
+        SpriteBundle {
+            sprite: Sprite {
+                color: CRUMBLE_TILE_COLOR,
+                custom_size: Some(Vec2::splat(tile_size)),
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, z_layers::PLATFORMS),
+            ..default()
+        },
+        RigidBody::Static,
+        Collider::rectangle(tile_size, tile_size),
+        SOLID_LAYERS,
+    ));
+}
+
+/// Starts the countdown of every solid crumble tile a player is standing on,
+/// whichever way gravity points. Touching one from the side or below leaves it
+/// alone.
+fn start_crumbling(
+    config: Res<CrumbleConfig>,
+    gravity_flip: Res<GravityFlip>,
+    players: Query<(&Transform, &CollidingEntities), With<Player>>,
+    mut tiles: Query<(&mut CrumbleTile, &ColliderAabb)>,
+) {
+    let up = gravity_flip.up();
+    for (transform, collisions) in &players {
+        let feet = transform.translation.y - PLAYER_HALF_HEIGHT * up;
+        for &entity in collisions.iter() {
+            let Ok((mut tile, aabb)) = tiles.get_mut(entity) else {
+                continue;
+            };
+            let underfoot = if up > 0.0 {
+                aabb.max.y <= feet + GROUND_TOLERANCE
+            } else {
+                aabb.min.y >= feet - GROUND_TOLERANCE
+            };
+            if tile.state == CrumbleState::Solid && underfoot {
+                tile.state = CrumbleState::Crumbling;
+                tile.timer = Timer::from_seconds(config.crumble_time, TimerMode::Once);
+            }
+        }
+    }
+}
+
+/// Fades crumbling tiles out, removes their collider once they give way and
+/// restores it after `respawn_time`. A tile only comes back once no player
+/// overlaps it, so nobody ends up stuck inside.
+fn update_crumble_tiles(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<CrumbleConfig>,
+    level_config: Res<LevelConfig>,
+    spatial_query: SpatialQuery,
+    mut tiles: Query<(Entity, &mut CrumbleTile, &mut Sprite, &Transform)>,
+) {
+    let tile_size = level_config.tile_size;
+    for (entity, mut tile, mut sprite, transform) in &mut tiles {
+        if tile.state == CrumbleState::Solid {
+            continue;
+        }
+        tile.timer.tick(time.delta());
+
+        match tile.state {
+            CrumbleState::Crumbling => {
+                sprite.color.set_alpha(1.0 - tile.timer.fraction() * 0.6);
+                if tile.timer.finished() {
+                    commands.entity(entity).remove::<(RigidBody, Collider)>();
+                    sprite.color.set_alpha(0.1);
+                    tile.state = CrumbleState::Gone;
+                    tile.timer = Timer::from_seconds(config.respawn_time, TimerMode::Once);
+                }
+            }
+            CrumbleState::Gone => {
+                if !tile.timer.finished() {
+                    continue;
+                }
+                let collider = Collider::rectangle(tile_size, tile_size);
+                let blocked = !spatial_query
+                    .shape_intersections(
+                        &collider,
+                        transform.translation.truncate(),
+                        0.0,
+                        SpatialQueryFilter::from_mask(GameLayer::Player.bit()),
+                    )
+                    .is_empty();
+                if blocked {
+                    continue;
+                }
+                commands
+                    .entity(entity)
+                    .insert((RigidBody::Static, collider));
+                sprite.color.set_alpha(1.0);
+                tile.state = CrumbleState::Solid;
+            }
+            CrumbleState::Solid => {}
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! Map `'k'` keys and the `'D'` doors they unlock.
+
+use bevy::prelude::*;
+use bevy_xpbd_2d::prelude::*;
+
+use super::{quick_restart, LevelEntity, LevelReloaded, LevelRestarted};
+use crate::player::Player;
+use crate::{z_layers, GameplaySet, COLLECTIBLE_LAYERS, SOLID_LAYERS};
+
+const DOOR_OPEN_TIME: f32 = 0.3;
+
+pub(crate) struct KeyDoorPlugin;
+
+impl Plugin for KeyDoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Keys>()
+            .add_event::<KeyCollected>()
+            .add_event::<DoorOpened>()
+            .add_systems(
+                Update,
+                (
+                    reset_keys.after(quick_restart),
+                    collect_keys,
+                    unlock_doors,
+                    animate_doors,
+                )
+                    .chain()
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// Map `'k'`: a pickup that unlocks one door.
+#[derive(Component)]
+struct Key;
+
+/// Map `'D'`: solid until the player touches it while holding a key. Touching
+/// `'D'` cells form one door: they share the id and open together for one key.
+#[derive(Component)]
+pub(crate) struct Door(u32);
+
+#[derive(Component, Deref, DerefMut)]
+struct DoorOpening(Timer);
+
+/// Keys collected and not yet spent on a door in the current level.
+#[derive(Resource, Default)]
+struct Keys(u32);
+
+#[derive(Event)]
+struct KeyCollected;
+
+#[derive(Event)]
+struct DoorOpened;
+
+pub(super) fn spawn_key(commands: &mut Commands, position: Vec2, tile_size: f32) {
+    commands.spawn((
+        Key,
+        LevelEntity,
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgb(1.0, 0.85, 0.2),
+                custom_size: Some(Vec2::splat(tile_size * 0.4)),
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, z_layers::ITEMS),
+            ..default()
+        },
+        RigidBody::Static,
+        Collider::rectangle(tile_size * 0.4, tile_size * 0.4),
+        Sensor,
+        COLLECTIBLE_LAYERS,
+    ));
+}
+
+pub(super) fn spawn_door(commands: &mut Commands, position: Vec2, tile_size: f32, group: u32) {
+    commands.spawn((
+        Door(group),
+        LevelEntity,
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgb(0.55, 0.35, 0.15),
+                custom_size: Some(Vec2::new(tile_size * 0.6, tile_size)),
+                ..default()
+            },
+            transform: Transform::from_xyz(position.x, position.y, z_layers::PLATFORMS),
+            ..default()
+        },
+        RigidBody::Static,
+        Collider::rectangle(tile_size * 0.6, tile_size),
+        SOLID_LAYERS,
+    ));
+}
+
+/// Respawned levels bring fresh keys and closed doors, so the count starts over.
+fn reset_keys(
+    mut restarted: EventReader<LevelRestarted>,
+    mut reloaded: EventReader<LevelReloaded>,
+    mut keys: ResMut<Keys>,
+) {
+    let restarted = restarted.read().last().is_some();
+    if reloaded.read().last().is_some() || restarted {
+        keys.0 = 0;
+    }
+}
+
+fn collect_keys(
+    mut commands: Commands,
+    mut keys: ResMut<Keys>,
+    player: Query<&CollidingEntities, With<Player>>,
+    pickups: Query<(), With<Key>>,
+    mut collected: EventWriter<KeyCollected>,
+) {
+    // Both players can touch one key in the same frame; it still counts once.
+    let mut taken = Vec::new();
+    for collisions in &player {
+        for &entity in collisions.iter() {
+            if pickups.contains(entity) && !taken.contains(&entity) {
+                taken.push(entity);
+                commands.entity(entity).despawn_recursive();
+                keys.0 += 1;
+                collected.send(KeyCollected);
+            }
+        }
+    }
+}
+
+fn unlock_doors(
+    mut commands: Commands,
+    mut keys: ResMut<Keys>,
+    player: Query<&CollidingEntities, With<Player>>,
+    closed: Query<&Door, (Without<DoorOpening>, With<Collider>)>,
+    doors: Query<(Entity, &Door)>,
+    mut opened: EventWriter<DoorOpened>,
+) {
+    let mut touched: Vec<u32> = player
+        .iter()
+        .flat_map(|collisions| collisions.iter())
+        .filter_map(|&entity| closed.get(entity).ok())
+        .map(|door| door.0)
+        .collect();
+    touched.sort_unstable();
+    touched.dedup();
+
+    for group in touched {
+        if keys.0 == 0 {
+            return;
+        }
+        keys.0 -= 1;
+        for (entity, door) in &doors {
+            if door.0 == group {
+                commands
+                    .entity(entity)
+                    .remove::<(RigidBody, Collider)>()
+                    .insert(DoorOpening(Timer::from_seconds(
+                        DOOR_OPEN_TIME,
+                        TimerMode::Once,
+                    )));
+            }
+        }
+        opened.send(DoorOpened);
+    }
+}
+
+/// Fades an unlocked door out; it stays open for the rest of the level.
+fn animate_doors(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut doors: Query<(Entity, &mut DoorOpening, &mut Sprite)>,
+) {
+    for (entity, mut opening, mut sprite) in &mut doors {
+        opening.tick(time.delta());
+        sprite.color.set_alpha(1.0 - opening.fraction() * 0.85);
+        if opening.finished() {
+            commands.entity(entity).remove::<DoorOpening>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::PlayerConfig;
+    use crate::testing::{press, step, test_app, TEST_ROOM};
+
+    fn door_app(map: &[&str]) -> App {
+        let mut app = test_app(map);
+        app.add_plugins(KeyDoorPlugin);
+        app
+    }
+
+    #[test]
+    fn a_key_touched_by_both_players_counts_once() {
+        let mut app = door_app(&["#######", "#P..k.#", "#######"]);
+        app.insert_resource(PlayerConfig {
+            coop: true,
+            ..default()
+        });
+        step(&mut app, 1);
+
+        let world = app.world_mut();
+        let key = world
+            .query_filtered::<&Transform, With<Key>>()
+            .single(world)
+            .translation;
+        let mut players = world.query_filtered::<&mut Position, With<Player>>();
+        assert_eq!(players.iter(world).count(), 2);
+        for mut position in players.iter_mut(world) {
+            position.0 = key.truncate();
+        }
+        step(&mut app, 3);
+
+        assert_eq!(app.world().resource::<Keys>().0, 1);
+    }
+
+    #[test]
+    fn a_hot_reload_takes_the_keys_away() {
+        let mut app = door_app(TEST_ROOM);
+        step(&mut app, 1);
+        app.insert_resource(Keys(2));
+        app.world_mut().send_event(LevelReloaded);
+        step(&mut app, 1);
+        assert_eq!(app.world().resource::<Keys>().0, 0);
+    }
+
+    #[test]
+    fn stacked_door_cells_open_together_for_one_key() {
+        let mut app = door_app(&["#######", "#...D.#", "#...D.#", "#P..D.#", "#######"]);
+        app.insert_resource(Keys(1));
+        step(&mut app, 30);
+
+        press(&mut app, KeyCode::KeyD);
+        step(&mut app, 60);
+
+        let world = app.world_mut();
+        let mut doors = world.query_filtered::<Has<Collider>, With<Door>>();
+        assert_eq!(doors.iter(world).count(), 3);
+        assert!(
+            doors.iter(world).all(|solid| !solid),
+            "a door cell stayed shut"
+        );
+        assert_eq!(app.world().resource::<Keys>().0, 0);
+    }
+}
@@ -0,0 +1,1871 @@
+//! KyberCheliK, a small 2D platformer on Bevy and bevy_xpbd.
+//!
+//! [`GamePlugin`] adds the whole game. The [`player`] and [`level`] modules are
+//! the surface other plugins can build on: their `pub` components, events and
+//! config resources are meant to stay put, while anything `pub(crate)` is an
+//! internal detail that may change at any time.
+
+use bevy::diagnostic::{
+    Diagnostic, DiagnosticPath, Diagnostics, DiagnosticsStore, RegisterDiagnostic,
+};
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::ImageSampler;
+use bevy::sprite::{Anchor, SpriteBundle};
+use bevy::utils::{HashMap, Instant};
+use bevy::window::WindowFocused;
+use bevy_xpbd_2d::prelude::*;
+use bevy_xpbd_2d::{PhysicsSchedule, PhysicsStepSet};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{fs, io};
+
+pub mod level;
+pub mod player;
+#[cfg(test)]
+mod testing;
+
+use level::{
+    checkpoints::CheckpointPlugin, crumble::CrumblePlugin, keys::KeyDoorPlugin, level_path,
+    load_level, quick_restart, setup_level, spawn_level, spawn_tile, LevelConfig, LevelData,
+    LevelEntity, LevelPlugin, LevelReloaded, LevelRestarted, LevelTile,
+};
+use player::{
+    interpolate_player_positions, update_player_state, AccessibilityConfig, AssistMode,
+    ContactSides, DashTimers, Facing, GravityFlip, GroundingCache, InterpolatedPosition,
+    JumpForgiveness, Landed, Player, PlayerAssets, PlayerConfig, PlayerPlugin, PlayerState,
+    TimingConfig,
+};
+
+pub const WINDOW_WIDTH: f32 = 1280.0;
+pub const WINDOW_HEIGHT: f32 = 720.0;
+const DEFAULT_TILE_SIZE: f32 = 48.0;
+const PLAYER_SIZE: Vec2 = Vec2::new(32.0, 48.0);
+/// Physics box of the player. Spawning and grounding both derive from this.
+const PLAYER_COLLIDER_SIZE: Vec2 = PLAYER_SIZE;
+const PLAYER_HALF_HEIGHT: f32 = PLAYER_COLLIDER_SIZE.y * 0.5;
+/// How far above the player's feet a tile top may sit and still count as floor.
+const GROUND_TOLERANCE: f32 = 2.0;
+/// Physics step rate. bevy_xpbd 0.5 has no swept collision, so walls stay solid
+/// only because every step moves the player a bounded distance: a slow frame
+/// runs extra steps (or slows the game down) instead of one long step. At
+/// 60 Hz a 820 px/s dash covers under 14 px per step, far less than the
+/// thinnest wall, a door, plus the player's own width. Keep it fixed, and keep
+/// `dash_speed / PHYSICS_HZ` well below that if either changes.
+const PHYSICS_HZ: f64 = 60.0;
+/// Strength of gravity in pixels per second squared; it pulls down unless
+/// flipped.
+const GRAVITY: f32 = 1500.0;
+const PLAYER_SPAWN: Vec2 = Vec2::new(-400.0, 200.0);
+const BACKGROUND_SRGB: [f32; 3] = [0.08, 0.09, 0.12];
+const BACKGROUND_COLOR: Color =
+    Color::srgb(BACKGROUND_SRGB[0], BACKGROUND_SRGB[1], BACKGROUND_SRGB[2]);
+const TILE_SRGB: [f32; 3] = [0.20, 0.22, 0.25];
+
+/// Physics layers. Who interacts with whom:
+///
+/// |             | Player | Solid | Hazard | Collectible | Enemy |
+/// |-------------|--------|-------|--------|-------------|-------|
+/// | Player      |        | x     | x      | x           | x     |
+/// | Solid       | x      |       |        |             | x     |
+/// | Hazard      | x      |       |        |             |       |
+/// | Collectible | x      |       |        |             |       |
+/// | Enemy       | x      | x     |        |             |       |
+///
+/// Co-op players pass through each other. Hazards and collectibles are sensors,
+/// so they only report overlaps.
+#[derive(Clone, Copy)]
+enum GameLayer {
+    Player,
+    Solid,
+    Hazard,
+    Collectible,
+    Enemy,
+}
+
+impl GameLayer {
+    const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+const PLAYER_LAYERS: CollisionLayers = CollisionLayers::from_bits(
+    GameLayer::Player.bit(),
+    GameLayer::Solid.bit()
+        | GameLayer::Hazard.bit()
+        | GameLayer::Collectible.bit()
+        | GameLayer::Enemy.bit(),
+);
+const SOLID_LAYERS: CollisionLayers = CollisionLayers::from_bits(
+    GameLayer::Solid.bit(),
+    GameLayer::Player.bit() | GameLayer::Enemy.bit(),
+);
+const COLLECTIBLE_LAYERS: CollisionLayers =
+    CollisionLayers::from_bits(GameLayer::Collectible.bit(), GameLayer::Player.bit());
+
+/// Draw order of world sprites, back to front. Spawn sites use these instead of
+/// bare Z values so new content slots in without overlapping the wrong thing.
+mod z_layers {
+    // Nothing is drawn behind the tiles or as an enemy yet.
+    #[allow(dead_code)]
+    pub const BACKGROUND: f32 = -10.0;
+    pub const TILES: f32 = 0.0;
+    /// Doors and other solid pieces drawn over the tiles they sit in.
+    pub const PLATFORMS: f32 = 0.2;
+    /// Pickups: in front of tiles, behind the player.
+    pub const ITEMS: f32 = 0.5;
+    #[allow(dead_code)]
+    pub const ENEMIES: f32 = 0.8;
+    /// Dash afterimages go just below this so they trail behind the player.
+    pub const PLAYER: f32 = 1.0;
+    pub const PARTICLES: f32 = 2.0;
+    /// World-space overlays that follow the camera; add small offsets to
+    /// stack several of them.
+    pub const UI_WORLD: f32 = 4.0;
+
+    /// Where `Camera2dBundle::default()` places the camera: just inside its
+    /// far plane, looking down -Z.
+    const CAMERA: f32 = 999.9;
+
+    /// Local Z for a child of the camera that should draw at world `layer`.
+    /// Children sit relative to the camera, so a positive Z would put them
+    /// behind it where they are never drawn.
+    pub fn camera_local(layer: f32) -> f32 {
+        layer - CAMERA
+    }
+}
+
+/// Everything that makes up the game: physics, the level, the players and
+/// the tools around them. Add it after `DefaultPlugins`.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClearColor(BACKGROUND_COLOR))
+            .insert_resource(Gravity(Vec2::NEG_Y * GRAVITY))
+            .insert_resource(Time::new_with(Physics::fixed_hz(PHYSICS_HZ)))
+            .add_plugins(PhysicsPlugins::default())
+            .add_plugins(PausePlugin)
+            .add_plugins(RngPlugin)
+            .add_plugins(LevelPlugin)
+            .add_plugins(PlayerPlugin)
+            .add_plugins(SavePlugin)
+            .add_plugins(MinimapPlugin)
+            .add_plugins(KeyDoorPlugin)
+            .add_plugins(CrumblePlugin)
+            .add_plugins(CheckpointPlugin)
+            .add_plugins(AssistPlugin)
+            .add_plugins(CameraPlugin)
+            .add_plugins(ParticlePlugin)
+            .add_plugins(EditorPlugin)
+            .add_plugins(StatsPlugin);
+    }
+}
+
+// --- Pause -----------------------------------------------------------------
+
+struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_state::<GameState>()
+            .configure_sets(Update, GameplaySet.run_if(in_state(GameState::Playing)))
+            .configure_sets(PostUpdate, GameplaySet.run_if(in_state(GameState::Playing)))
+            .add_systems(Startup, setup_pause_overlay)
+            .add_systems(Update, (toggle_pause, pause_on_focus_loss))
+            .add_systems(
+                OnEnter(GameState::Paused),
+                (pause_physics, show_pause_overlay),
+            )
+            .add_systems(
+                OnExit(GameState::Paused),
+                (resume_physics, hide_pause_overlay),
+            );
+    }
+}
+
+#[derive(States, Default, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GameState {
+    #[default]
+    Playing,
+    Paused,
+}
+
+/// Systems that advance the game: input, movement, timers, effects. They only run
+/// while [`GameState::Playing`], so no gameplay timer ticks during a pause.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+struct GameplaySet;
+
+fn toggle_pause(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if !keyboard.just_pressed(KeyCode::Escape) {
+        return;
+    }
+
+    next_state.set(match state.get() {
+        GameState::Playing => GameState::Paused,
+        GameState::Paused => GameState::Playing,
+    });
+}
+
+/// Pauses when the window loses focus. Regaining focus doesn't resume; the player
+/// does that themselves once they're ready.
+fn pause_on_focus_loss(
+    mut focus: EventReader<WindowFocused>,
+    accessibility: Res<AccessibilityConfig>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    let lost_focus = focus.read().any(|event| !event.focused);
+    if lost_focus && accessibility.pause_on_focus_loss && *state.get() == GameState::Playing {
+        next_state.set(GameState::Paused);
+    }
+}
+
+/// Dims the view while paused. It sits above the level but below the editor
+/// cursor.
+#[derive(Component)]
+struct PauseOverlay;
+
+fn setup_pause_overlay(mut commands: Commands) {
+    commands.spawn((
+        PauseOverlay,
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgba(0.0, 0.0, 0.0, 0.45),
+                custom_size: Some(Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, z_layers::UI_WORLD),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// The camera doesn't move while paused, so placing the overlay once is enough.
+fn show_pause_overlay(
+    camera: Query<&Transform, (With<MainCamera>, Without<PauseOverlay>)>,
+    mut overlay: Query<(&mut Transform, &mut Visibility), With<PauseOverlay>>,
+) {
+    let (Ok(camera), Ok((mut transform, mut visibility))) =
+        (camera.get_single(), overlay.get_single_mut())
+    else {
+        return;
+    };
+    transform.translation.x = camera.translation.x;
+    transform.translation.y = camera.translation.y;
+    *visibility = Visibility::Visible;
+}
+
+fn hide_pause_overlay(mut overlay: Query<&mut Visibility, With<PauseOverlay>>) {
+    for mut visibility in &mut overlay {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+fn pause_physics(mut time: ResMut<Time<Physics>>) {
+    time.pause();
+}
+
+/// Drops any time accumulated for fixed physics steps before unpausing, so the
+/// first frame after a pause doesn't run a burst of catch-up steps and bodies
+/// resume with exactly the velocity they had.
+fn resume_physics(mut time: ResMut<Time<Physics>>) {
+    if let TimestepMode::Fixed { overstep, .. } = time.timestep_mode_mut() {
+        *overstep = Duration::ZERO;
+    }
+    time.unpause();
+}
+
+// --- Randomness ------------------------------------------------------------
+
+/// Seed used unless another [`RngSeed`] is inserted.
+const DEFAULT_RNG_SEED: u64 = 0x6b79_6265_7263;
+
+/// Seeds [`GameRng`] and reseeds it on every level restart, so each attempt sees
+/// the same sequence of random effects.
+struct RngPlugin;
+
+impl Plugin for RngPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RngSeed>();
+        let seed = app.world().resource::<RngSeed>().0;
+        info!("rng seed {seed}");
+        app.insert_resource(GameRng::new(seed))
+            .add_systems(Update, reseed_on_restart.after(quick_restart));
+    }
+}
+
+/// Seed of the gameplay randomness. Insert it before [`GamePlugin`] to replay a
+/// run; the game binary reads it from `--seed <n>`.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct RngSeed(pub u64);
+
+impl Default for RngSeed {
+    fn default() -> Self {
+        Self(DEFAULT_RNG_SEED)
+    }
+}
+
+/// The only source of gameplay randomness. Draw from this instead of `rand::random`
+/// so a run can be reproduced from its [`RngSeed`].
+#[derive(Resource)]
+struct GameRng(ChaCha8Rng);
+
+impl GameRng {
+    fn new(seed: u64) -> Self {
+        Self(ChaCha8Rng::seed_from_u64(seed))
+    }
+}
+
+fn reseed_on_restart(
+    mut restarted: EventReader<LevelRestarted>,
+    seed: Res<RngSeed>,
+    mut rng: ResMut<GameRng>,
+) {
+    if restarted.read().last().is_some() {
+        *rng = GameRng::new(seed.0);
+    }
+}
+
+// --- Camera ----------------------------------------------------------------
+
+struct CameraPlugin;
+
+impl Plugin for CameraPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CameraConfig>()
+            .init_resource::<PixelPerfect>()
+            .add_systems(Startup, setup_camera)
+            .add_systems(
+                Update,
+                (
+                    toggle_pixel_perfect,
+                    apply_pixel_perfect.run_if(resource_changed::<PixelPerfect>),
+                )
+                    .chain(),
+            )
+            .add_systems(
+                PostUpdate,
+                (follow_player.in_set(GameplaySet), snap_player_sprites)
+                    .chain()
+                    .after(interpolate_player_positions)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+/// Camera tuning. Each frame the target is built in this order: the dead zone
+/// moves the focus point just enough to keep the players inside it, the
+/// look-ahead and vertical bias offset that focus, the result is clamped to
+/// the level's top edge, and the camera eases towards it.
+#[derive(Resource)]
+struct CameraConfig {
+    /// Half-extents of the box around the focus point the players may move in
+    /// without the camera following, in pixels.
+    dead_zone: Vec2,
+    /// How quickly the camera closes the gap to its target, per second.
+    follow_speed: f32,
+    /// Offset towards the player's facing direction, in pixels.
+    look_ahead: f32,
+    /// Extra offset per unit of horizontal velocity.
+    velocity_look_ahead: f32,
+    /// Upper bound on the combined horizontal offset so dashes don't fling the view.
+    max_look_ahead: f32,
+    /// How quickly the offset eases towards a new direction, per second.
+    look_ahead_speed: f32,
+    /// Upward offset while the players are rising, so the landing spot stays in view.
+    vertical_bias: f32,
+    /// How quickly the vertical offset eases in and out, per second.
+    vertical_bias_speed: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            dead_zone: Vec2::new(40.0, 64.0),
+            follow_speed: 6.0,
+            look_ahead: 80.0,
+            velocity_look_ahead: 0.15,
+            max_look_ahead: 160.0,
+            look_ahead_speed: 3.0,
+            vertical_bias: 90.0,
+            vertical_bias_speed: 4.0,
+        }
+    }
+}
+
+#[derive(Component)]
+struct MainCamera;
+
+/// Current eased horizontal look-ahead offset of the camera.
+#[derive(Component, Default)]
+struct LookAhead(f32);
+
+/// Current eased vertical offset of the camera, see [`CameraConfig::vertical_bias`].
+#[derive(Component, Default)]
+struct VerticalBias(f32);
+
+/// Point the camera frames, dragged along by the players at the edge of
+/// [`CameraConfig::dead_zone`].
+#[derive(Component, Default)]
+struct DeadZoneFocus(Vec2);
+
+/// Unrounded camera position. With [`PixelPerfect`] the transform is rounded
+/// to whole pixels, so the easing has to continue from this instead.
+#[derive(Component, Default)]
+struct SmoothedPosition(Vec2);
+
+/// Crisp pixel-art rendering: whole-pixel camera and player positions, an
+/// integer projection scale and nearest-neighbour sampling. Saved with the
+/// other settings and toggled with F7.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone, Copy, Default)]
+struct PixelPerfect(bool);
+
+fn setup_camera(mut commands: Commands) {
+    commands.spawn((
+        Camera2dBundle::default(),
+        MainCamera,
+        LookAhead::default(),
+        VerticalBias::default(),
+        DeadZoneFocus::default(),
+        SmoothedPosition::default(),
+    ));
+}
+
+fn toggle_pixel_perfect(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut pixel_perfect: ResMut<PixelPerfect>,
+    mut save: ResMut<SaveData>,
+) {
+    if keyboard.just_pressed(KeyCode::F7) {
+        pixel_perfect.0 = !pixel_perfect.0;
+        save.pixel_perfect = *pixel_perfect;
+        info!(
+            "pixel-perfect rendering {}",
+            if pixel_perfect.0 { "on" } else { "off" }
+        );
+    }
+}
+
+/// Switches the player texture's sampling and keeps the projection at a whole
+/// number of screen pixels per world pixel.
+fn apply_pixel_perfect(
+    pixel_perfect: Res<PixelPerfect>,
+    assets: Option<Res<PlayerAssets>>,
+    mut images: ResMut<Assets<Image>>,
+    mut projection: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    if let Some(image) = assets.and_then(|assets| images.get_mut(&assets.texture)) {
+        image.sampler = if pixel_perfect.0 {
+            ImageSampler::nearest()
+        } else {
+            ImageSampler::Default
+        };
+    }
+
+    if pixel_perfect.0 {
+        if let Ok(mut projection) = projection.get_single_mut() {
+            let zoom = (1.0 / projection.scale).round().max(1.0);
+            projection.scale = 1.0 / zoom;
+        }
+    }
+}
+
+/// Draws the player sprites at their [`InterpolatedPosition`], and with
+/// [`PixelPerfect`] rounds that to whole pixels, so interpolation smooths the
+/// motion first and snapping then keeps it crisp. Only the anchor moves; the
+/// physics transform is untouched.
+fn snap_player_sprites(
+    pixel_perfect: Res<PixelPerfect>,
+    config: Res<PlayerConfig>,
+    mut players: Query<(&Transform, &InterpolatedPosition, &mut Sprite), With<Player>>,
+) {
+    for (transform, interpolated, mut sprite) in &mut players {
+        let physics = transform.translation.truncate();
+        let mut drawn = interpolated.rendered + config.sprite_offset;
+        if pixel_perfect.0 {
+            drawn = drawn.round();
+        }
+        let size = sprite.custom_size.unwrap_or(PLAYER_SIZE);
+        sprite.anchor = Anchor::Custom(-(drawn - physics) / size);
+    }
+}
+
+fn follow_player(
+    time: Res<Time>,
+    config: Res<CameraConfig>,
+    pixel_perfect: Res<PixelPerfect>,
+    level: Res<LevelData>,
+    level_config: Res<LevelConfig>,
+    player: Query<(&InterpolatedPosition, &Facing, &LinearVelocity), With<Player>>,
+    mut camera: Query<
+        (
+            &mut Transform,
+            &mut LookAhead,
+            &mut VerticalBias,
+            &mut DeadZoneFocus,
+            &mut SmoothedPosition,
+            &OrthographicProjection,
+        ),
+        (With<MainCamera>, Without<Player>),
+    >,
+) {
+    let Ok((mut transform, mut look_ahead, mut vertical_bias, mut focus, mut smoothed, projection)) =
+        camera.get_single_mut()
+    else {
+        return;
+    };
+    let count = player.iter().len();
+    if count == 0 {
+        return;
+    }
+
+    // With several players the camera follows their average, looking ahead by
+    // their average facing and speed.
+    let (position, facing, velocity) = player.iter().fold(
+        (Vec2::ZERO, 0.0, Vec2::ZERO),
+        |(position, facing, velocity), (interpolated, player_facing, player_velocity)| {
+            (
+                position + interpolated.rendered,
+                facing + player_facing.0,
+                velocity + player_velocity.0,
+            )
+        },
+    );
+    let count = count as f32;
+    let (position, facing, velocity) = (position / count, facing / count, velocity / count);
+
+    // Drag the focus only as far as needed to bring the players back to the
+    // box edge.
+    let min = position - config.dead_zone;
+    let max = position + config.dead_zone;
+    focus.0 = focus.0.clamp(min, max);
+
+    let dt = time.delta_seconds();
+    let target_offset = (facing * config.look_ahead + velocity.x * config.velocity_look_ahead)
+        .clamp(-config.max_look_ahead, config.max_look_ahead);
+    look_ahead.0 += (target_offset - look_ahead.0) * (1.0 - (-config.look_ahead_speed * dt).exp());
+
+    // Only lift the view once a rise carries the players out of the top of the
+    // box, so hops inside it leave the camera still.
+    let pushing_up = position.y - focus.0.y >= config.dead_zone.y - 1.0;
+    let target_bias = if velocity.y > 0.0 && pushing_up {
+        config.vertical_bias
+    } else {
+        0.0
+    };
+    vertical_bias.0 +=
+        (target_bias - vertical_bias.0) * (1.0 - (-config.vertical_bias_speed * dt).exp());
+
+    let target = clamp_view_to_level(
+        focus.0 + Vec2::new(look_ahead.0, vertical_bias.0),
+        level.bounds(&level_config),
+        projection.area.half_size(),
+    );
+    smoothed.0 = smoothed
+        .0
+        .lerp(target, 1.0 - (-config.follow_speed * dt).exp());
+    let next = if pixel_perfect.0 {
+        smoothed.0.round()
+    } else {
+        smoothed.0
+    };
+    transform.translation.x = next.x;
+    transform.translation.y = next.y;
+}
+
+/// Moves the centre of a view `half_size` across the smallest distance that
+/// keeps it inside `bounds`, so the camera never shows space outside the level.
+/// On an axis where the level is narrower than the view, the view is centred.
+fn clamp_view_to_level(target: Vec2, bounds: Rect, half_size: Vec2) -> Vec2 {
+    let min = bounds.min + half_size;
+    let max = bounds.max - half_size;
+    let axis = |value: f32, min: f32, max: f32| {
+        if min > max {
+            (min + max) * 0.5
+        } else {
+            value.clamp(min, max)
+        }
+    };
+    Vec2::new(axis(target.x, min.x, max.x), axis(target.y, min.y, max.y))
+}
+
+// --- Particles -------------------------------------------------------------
+
+/// Puff size for the softest and the hardest landings; falls in between scale
+/// linearly.
+const DUST_PARTICLES: RangeInclusive<usize> = 3..=14;
+const DUST_SIZE: RangeInclusive<f32> = 3.0..=7.0;
+/// Landing speeds, in pixels per second, for the smallest puff and the biggest.
+/// Below the first nothing is spawned, so stepping off a lip stays quiet.
+const DUST_IMPACT: RangeInclusive<f32> = 300.0..=1200.0;
+const DUST_LIFETIME: f32 = 0.4;
+
+struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GraphicsQuality>()
+            .add_systems(Update, toggle_particles)
+            .add_systems(
+                Update,
+                (
+                    spawn_landing_dust.after(update_player_state),
+                    update_particles,
+                )
+                    .in_set(GameplaySet),
+            );
+    }
+}
+
+/// Effect settings for slower machines, saved with the other settings. Every
+/// system that spawns effects checks these first.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct GraphicsQuality {
+    particles: bool,
+    /// Dash afterimages; honoured by afterimage effects once they exist.
+    afterimages: bool,
+    /// Camera shake; honoured by shake effects once they exist.
+    screen_shake: bool,
+    /// Upper bound on live particles; spawns beyond it are dropped.
+    max_particles: usize,
+}
+
+impl Default for GraphicsQuality {
+    fn default() -> Self {
+        Self {
+            particles: true,
+            afterimages: true,
+            screen_shake: true,
+            max_particles: 256,
+        }
+    }
+}
+
+/// F8 switches particles on and off and saves the choice.
+fn toggle_particles(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut quality: ResMut<GraphicsQuality>,
+    mut save: ResMut<SaveData>,
+) {
+    if keyboard.just_pressed(KeyCode::F8) {
+        quality.particles = !quality.particles;
+        save.graphics = quality.clone();
+        info!("particles {}", if quality.particles { "on" } else { "off" });
+    }
+}
+
+#[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+}
+
+/// Fades a sprite out over its duration and despawns it at the end.
+#[derive(Component, Deref, DerefMut)]
+struct FadeTimer(Timer);
+
+fn spawn_landing_dust(
+    mut commands: Commands,
+    mut rng: ResMut<GameRng>,
+    quality: Res<GraphicsQuality>,
+    gravity_flip: Res<GravityFlip>,
+    mut landed: EventReader<Landed>,
+    transforms: Query<&GlobalTransform>,
+    particles: Query<(), With<Particle>>,
+) {
+    let mut live = particles.iter().len();
+    for event in landed.read() {
+        if event.speed < *DUST_IMPACT.start() || !quality.particles {
+            continue;
+        }
+        let Ok(transform) = transforms.get(event.entity) else {
+            continue;
+        };
+
+        let impact = ((event.speed - DUST_IMPACT.start())
+            / (DUST_IMPACT.end() - DUST_IMPACT.start()))
+        .min(1.0);
+        let wanted = DUST_PARTICLES.start()
+            + ((DUST_PARTICLES.end() - DUST_PARTICLES.start()) as f32 * impact).round() as usize;
+        let size = DUST_SIZE.start() + (DUST_SIZE.end() - DUST_SIZE.start()) * impact;
+        let count = wanted.min(quality.max_particles.saturating_sub(live));
+        live += count;
+        let up = gravity_flip.up();
+        let feet = transform.translation().truncate() - Vec2::new(0.0, PLAYER_HALF_HEIGHT * up);
+        for i in 0..count {
+            // Fan the puff out sideways, slightly upwards.
+            let t = if count > 1 {
+                i as f32 / (count - 1) as f32
+            } else {
+                0.5
+            };
+            let direction = Vec2::new(t * 2.0 - 1.0, 0.35 * up).normalize();
+            let direction = Vec2::from_angle(rng.0.gen_range(-0.15..=0.15)).rotate(direction);
+            let speed = rng.0.gen_range(70.0..=110.0) * (1.0 + impact);
+            commands.spawn((
+                Particle {
+                    velocity: direction * speed,
+                },
+                FadeTimer(Timer::from_seconds(DUST_LIFETIME, TimerMode::Once)),
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::srgb(0.8, 0.8, 0.75),
+                        custom_size: Some(Vec2::splat(size)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(feet.x, feet.y, z_layers::PARTICLES),
+                    ..default()
+                },
+            ));
+        }
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(
+        Entity,
+        &mut FadeTimer,
+        &mut Sprite,
+        &mut Transform,
+        Option<&Particle>,
+    )>,
+) {
+    for (entity, mut fade, mut sprite, mut transform, particle) in &mut particles {
+        fade.tick(time.delta());
+        if fade.finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+
+        if let Some(particle) = particle {
+            transform.translation += (particle.velocity * time.delta_seconds()).extend(0.0);
+        }
+        sprite.color.set_alpha(1.0 - fade.fraction());
+    }
+}
+
+// --- Assist mode -----------------------------------------------------------
+
+const ASSIST_BADGE_SIZE: f32 = 12.0;
+const ASSIST_BADGE_MARGIN: f32 = 16.0;
+
+/// F9 toggles [`AssistMode`], which slows the game down and lifts the jump and
+/// dash limits. A small badge in the top-left corner shows while any assist is
+/// in effect.
+struct AssistPlugin;
+
+impl Plugin for AssistPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup_assist_badge.after(setup_camera))
+            .add_systems(
+                Update,
+                (
+                    toggle_assist_mode,
+                    (apply_assist_speed, update_assist_badge)
+                        .run_if(resource_changed::<AssistMode>),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Component)]
+struct AssistBadge;
+
+fn setup_assist_badge(mut commands: Commands, camera: Query<Entity, With<MainCamera>>) {
+    let corner = Vec2::new(-WINDOW_WIDTH, WINDOW_HEIGHT) * 0.5
+        + Vec2::new(1.0, -1.0) * (ASSIST_BADGE_MARGIN + ASSIST_BADGE_SIZE * 0.5);
+    let badge = commands
+        .spawn((
+            AssistBadge,
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::srgba(0.45, 0.85, 0.55, 0.8),
+                    custom_size: Some(Vec2::splat(ASSIST_BADGE_SIZE)),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    corner.x,
+                    corner.y,
+                    z_layers::camera_local(z_layers::UI_WORLD + 2.0),
+                ),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .id();
+    commands.entity(camera.single()).add_child(badge);
+}
+
+fn toggle_assist_mode(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut assist: ResMut<AssistMode>,
+    mut save: ResMut<SaveData>,
+) {
+    if keyboard.just_pressed(KeyCode::F9) {
+        assist.enabled = !assist.enabled;
+        save.assist = assist.clone();
+        info!("assist mode {}", if assist.enabled { "on" } else { "off" });
+    }
+}
+
+/// Slows gameplay and physics by the same factor, so the fixed physics step
+/// still lines up with the frame timers.
+fn apply_assist_speed(
+    assist: Res<AssistMode>,
+    mut virtual_time: ResMut<Time<Virtual>>,
+    mut physics_time: ResMut<Time<Physics>>,
+) {
+    let speed = assist.speed();
+    virtual_time.set_relative_speed(speed);
+    physics_time.set_relative_speed(speed);
+}
+
+fn update_assist_badge(
+    assist: Res<AssistMode>,
+    mut badge: Query<&mut Visibility, With<AssistBadge>>,
+) {
+    for mut visibility in &mut badge {
+        *visibility = if assist.is_active() {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+// --- Minimap ---------------------------------------------------------------
+
+const MINIMAP_CELL: f32 = 6.0;
+const MINIMAP_MARGIN: f32 = 16.0;
+
+struct MinimapPlugin;
+
+impl Plugin for MinimapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Startup,
+            setup_minimap.after(setup_camera).after(setup_level),
+        )
+        .add_systems(
+            Update,
+            (
+                rebuild_minimap.after(quick_restart),
+                toggle_minimap,
+                update_minimap_marker,
+            ),
+        );
+    }
+}
+
+#[derive(Component)]
+struct Minimap;
+
+#[derive(Component)]
+struct MinimapMarker;
+
+#[derive(Resource)]
+struct MinimapImage(Handle<Image>);
+
+fn build_minimap_image(level: &LevelData) -> Image {
+    let mut data = Vec::new();
+    for line in &level.rows {
+        for ch in line.chars() {
+            let rgba: [u8; 4] = match ch {
+                '#' => [150, 160, 175, 255],
+                'T' => [110, 95, 80, 255],
+                _ => [20, 22, 30, 180],
+            };
+            data.extend_from_slice(&rgba);
+        }
+    }
+
+    let mut image = Image::new(
+        Extent3d {
+            width: level.width as u32,
+            height: level.height() as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+    image.sampler = ImageSampler::nearest();
+    image
+}
+
+fn minimap_size(level: &LevelData) -> Vec2 {
+    Vec2::new(level.width as f32, level.height() as f32) * MINIMAP_CELL
+}
+
+/// Camera-local centre that puts a minimap of `size` in the top-right corner.
+fn minimap_corner(size: Vec2) -> Vec2 {
+    Vec2::new(WINDOW_WIDTH, WINDOW_HEIGHT) * 0.5 - size * 0.5 - MINIMAP_MARGIN
+}
+
+fn setup_minimap(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    level: Res<LevelData>,
+    camera: Query<Entity, With<MainCamera>>,
+) {
+    let texture = images.add(build_minimap_image(&level));
+    let size = minimap_size(&level);
+    let corner = minimap_corner(size);
+
+    let minimap = commands
+        .spawn((
+            Minimap,
+            SpriteBundle {
+                texture: texture.clone(),
+                sprite: Sprite {
+                    custom_size: Some(size),
+                    ..default()
+                },
+                transform: Transform::from_xyz(
+                    corner.x,
+                    corner.y,
+                    z_layers::camera_local(z_layers::UI_WORLD + 2.0),
+                ),
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                MinimapMarker,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::srgb(1.0, 0.85, 0.2),
+                        custom_size: Some(Vec2::splat(MINIMAP_CELL)),
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(0.0, 0.0, 1.0),
+                    ..default()
+                },
+            ));
+        })
+        .id();
+
+    commands.entity(camera.single()).add_child(minimap);
+    commands.insert_resource(MinimapImage(texture));
+}
+
+fn rebuild_minimap(
+    mut restarted: EventReader<LevelRestarted>,
+    mut reloaded: EventReader<LevelReloaded>,
+    minimap: Res<MinimapImage>,
+    level: Res<LevelData>,
+    mut images: ResMut<Assets<Image>>,
+    mut sprites: Query<(&mut Sprite, &mut Transform), With<Minimap>>,
+) {
+    // Drain both readers, even if the first already has an event.
+    let restarted = restarted.read().last().is_some();
+    let reloaded = reloaded.read().last().is_some();
+    if restarted || reloaded {
+        images.insert(&minimap.0, build_minimap_image(&level));
+        // A reloaded level may have a different size.
+        let size = minimap_size(&level);
+        let corner = minimap_corner(size);
+        for (mut sprite, mut transform) in &mut sprites {
+            sprite.custom_size = Some(size);
+            transform.translation.x = corner.x;
+            transform.translation.y = corner.y;
+        }
+    }
+}
+
+fn toggle_minimap(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut query: Query<&mut Visibility, With<Minimap>>,
+) {
+    if !keyboard.just_pressed(KeyCode::KeyM) {
+        return;
+    }
+
+    for mut visibility in &mut query {
+        *visibility = match *visibility {
+            Visibility::Hidden => Visibility::Inherited,
+            _ => Visibility::Hidden,
+        };
+    }
+}
+
+fn update_minimap_marker(
+    level: Res<LevelData>,
+    config: Res<LevelConfig>,
+    player: Query<&GlobalTransform, With<Player>>,
+    mut marker: Query<&mut Transform, With<MinimapMarker>>,
+) {
+    // The marker tracks the first player.
+    let (Some(player), Ok(mut marker)) = (player.iter().next(), marker.get_single_mut()) else {
+        return;
+    };
+
+    let cell = level.world_to_grid(player.translation().truncate(), &config);
+    let top_left = minimap_size(&level) * Vec2::new(-0.5, 0.5);
+    let offset = Vec2::new(cell.x as f32 + 0.5, -(cell.y as f32 + 0.5)) * MINIMAP_CELL;
+    marker.translation.x = top_left.x + offset.x;
+    marker.translation.y = top_left.y + offset.y;
+}
+
+// --- Editor ----------------------------------------------------------------
+
+/// Minimal tile editor available while paused: the arrow keys move a grid cursor,
+/// Enter toggles a solid tile under it and F2 writes the layout to a text file.
+/// Edits are applied, restarting the level, when play resumes.
+struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LevelEdited>()
+            .add_systems(Startup, setup_editor_cursor.after(setup_level))
+            .add_systems(OnEnter(GameState::Paused), show_editor_cursor)
+            .add_systems(
+                OnExit(GameState::Paused),
+                (hide_editor_cursor, apply_level_edits),
+            )
+            .add_systems(
+                Update,
+                (move_editor_cursor, toggle_tile_at_cursor, export_level)
+                    .chain()
+                    .run_if(in_state(GameState::Paused)),
+            );
+    }
+}
+
+/// Grid cell the editor cursor is on.
+#[derive(Component)]
+struct EditorCursor(UVec2);
+
+/// Whether tiles were placed or erased since the level was last spawned.
+#[derive(Resource, Default)]
+struct LevelEdited(bool);
+
+fn setup_editor_cursor(mut commands: Commands, config: Res<LevelConfig>) {
+    commands.spawn((
+        EditorCursor(UVec2::ZERO),
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::srgba(1.0, 1.0, 1.0, 0.35),
+                custom_size: Some(Vec2::splat(config.tile_size)),
+                ..default()
+            },
+            transform: Transform::from_xyz(0.0, 0.0, z_layers::UI_WORLD + 1.0),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+    ));
+}
+
+/// Shows the cursor on the first player's cell.
+fn show_editor_cursor(
+    level: Res<LevelData>,
+    config: Res<LevelConfig>,
+    player: Query<&Transform, (With<Player>, Without<EditorCursor>)>,
+    mut cursor: Query<(&mut EditorCursor, &mut Transform, &mut Visibility)>,
+) {
+    let Ok((mut cell, mut transform, mut visibility)) = cursor.get_single_mut() else {
+        return;
+    };
+
+    if let Some(player) = player.iter().next() {
+        cell.0 = level.world_to_grid(player.translation.truncate(), &config);
+    }
+    let position = level.tile_center(cell.0.x as usize, cell.0.y as usize, &config);
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+    *visibility = Visibility::Visible;
+}
+
+fn hide_editor_cursor(mut cursor: Query<&mut Visibility, With<EditorCursor>>) {
+    for mut visibility in &mut cursor {
+        *visibility = Visibility::Hidden;
+    }
+}
+
+fn move_editor_cursor(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    level: Res<LevelData>,
+    config: Res<LevelConfig>,
+    mut cursor: Query<(&mut EditorCursor, &mut Transform)>,
+) {
+    let Ok((mut cell, mut transform)) = cursor.get_single_mut() else {
+        return;
+    };
+
+    let mut step = IVec2::ZERO;
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        step.x -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        step.x += 1;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        step.y -= 1;
+    }
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        step.y += 1;
+    }
+    if step == IVec2::ZERO {
+        return;
+    }
+
+    let max = IVec2::new(level.width as i32 - 1, level.height() as i32 - 1);
+    cell.0 = (cell.0.as_ivec2() + step)
+        .clamp(IVec2::ZERO, max)
+        .as_uvec2();
+    let position = level.tile_center(cell.0.x as usize, cell.0.y as usize, &config);
+    transform.translation.x = position.x;
+    transform.translation.y = position.y;
+}
+
+/// Places a solid tile on an empty cell or erases one. Keys, doors and the spawn
+/// are left alone.
+fn toggle_tile_at_cursor(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    level: Res<LevelData>,
+    config: Res<LevelConfig>,
+    mut edited: ResMut<LevelEdited>,
+    cursor: Query<&EditorCursor>,
+    tiles: Query<(Entity, &Transform), With<LevelTile>>,
+) {
+    if !keyboard.just_pressed(KeyCode::Enter) {
+        return;
+    }
+    let Ok(cursor) = cursor.get_single() else {
+        return;
+    };
+
+    let (col, row) = (cursor.0.x as usize, cursor.0.y as usize);
+    let editable = level.rows[row]
+        .chars()
+        .nth(col)
+        .is_some_and(|tile| tile == '#' || tile == '.');
+    if !editable {
+        return;
+    }
+
+    let existing = tiles.iter().find(|(_, transform)| {
+        level.world_to_grid(transform.translation.truncate(), &config) == cursor.0
+    });
+    match existing {
+        Some((entity, _)) => commands.entity(entity).despawn_recursive(),
+        None => spawn_tile(
+            &mut commands,
+            level.tile_center(col, row, &config),
+            config.tile_size,
+            level.theme.tile_color(),
+            None,
+        ),
+    }
+    edited.0 = true;
+}
+
+/// Rebuilds the map rows from the [`LevelTile`] entities: every cell with a tile
+/// is `'#'`, every other tile cell `'.'`, and keys, doors and the spawn are kept.
+fn map_from_tiles(
+    level: &LevelData,
+    config: &LevelConfig,
+    tiles: impl Iterator<Item = Vec2>,
+) -> Vec<String> {
+    let mut grid: Vec<Vec<char>> = level
+        .rows
+        .iter()
+        .map(|row| {
+            row.chars()
+                .map(|tile| if tile == '#' { '.' } else { tile })
+                .collect()
+        })
+        .collect();
+    for position in tiles {
+        let cell = level.world_to_grid(position, config);
+        grid[cell.y as usize][cell.x as usize] = '#';
+    }
+    grid.into_iter().map(String::from_iter).collect()
+}
+
+fn export_level(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    level: Res<LevelData>,
+    config: Res<LevelConfig>,
+    tiles: Query<&Transform, With<LevelTile>>,
+) {
+    if !keyboard.just_pressed(KeyCode::F2) {
+        return;
+    }
+
+    let rows = map_from_tiles(
+        &level,
+        &config,
+        tiles
+            .iter()
+            .map(|transform| transform.translation.truncate()),
+    );
+    let Some(path) = level_path() else {
+        warn!("no data directory available, level not exported");
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(&path, rows.join("\n") + "\n"));
+    match result {
+        Ok(()) => info!("exported level to {}", path.display()),
+        Err(err) => error!("failed to export level to {}: {err}", path.display()),
+    }
+}
+
+/// Respawns the level from the edited tiles when play resumes.
+fn apply_level_edits(
+    mut commands: Commands,
+    mut edited: ResMut<LevelEdited>,
+    mut level: ResMut<LevelData>,
+    config: Res<LevelConfig>,
+    tiles: Query<&Transform, With<LevelTile>>,
+    level_entities: Query<Entity, With<LevelEntity>>,
+    mut restarted: EventWriter<LevelRestarted>,
+) {
+    if !edited.0 {
+        return;
+    }
+    edited.0 = false;
+
+    let rows = map_from_tiles(
+        &level,
+        &config,
+        tiles
+            .iter()
+            .map(|transform| transform.translation.truncate()),
+    );
+    let theme = level.theme.clone();
+    *level = load_level(&rows);
+    level.theme = theme;
+    for entity in &level_entities {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_level(&mut commands, &level, &config, false);
+    restarted.send(LevelRestarted);
+}
+
+// --- Save data -------------------------------------------------------------
+
+struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        let data = load_game();
+        app.insert_resource(data.accessibility.clone())
+            .insert_resource(data.assist.clone())
+            .insert_resource(data.graphics.clone())
+            .insert_resource(data.pixel_perfect)
+            .insert_resource(data)
+            .add_systems(
+                Last,
+                (
+                    persist_save_data.run_if(
+                        resource_changed::<SaveData>.and_then(not(resource_added::<SaveData>)),
+                    ),
+                    save_on_exit,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// Progress and settings persisted between sessions.
+#[derive(Resource, Serialize, Deserialize, Default, Debug, Clone)]
+#[serde(default)]
+struct SaveData {
+    level_reached: usize,
+    best_times: HashMap<String, f32>,
+    coins_collected: u32,
+    audio: AudioSettings,
+    accessibility: AccessibilityConfig,
+    assist: AssistMode,
+    graphics: GraphicsQuality,
+    pixel_perfect: PixelPerfect,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+struct AudioSettings {
+    master_volume: f32,
+    music_volume: f32,
+    sfx_volume: f32,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        Self {
+            master_volume: 1.0,
+            music_volume: 1.0,
+            sfx_volume: 1.0,
+        }
+    }
+}
+
+fn save_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("kyberchelik").join("save.ron"))
+}
+
+fn load_game() -> SaveData {
+    let Some(path) = save_path() else {
+        warn!("no data directory available, progress will not be saved");
+        return SaveData::default();
+    };
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            warn!(
+                "could not read save file {}: {err}, starting fresh",
+                path.display()
+            );
+            return SaveData::default();
+        }
+    };
+
+    ron::from_str(&contents).unwrap_or_else(|err| {
+        warn!(
+            "save file {} is corrupt: {err}, starting fresh",
+            path.display()
+        );
+        SaveData::default()
+    })
+}
+
+fn save_game(data: &SaveData) -> io::Result<()> {
+    let path =
+        save_path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let contents = ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::default())
+        .map_err(io::Error::other)?;
+    fs::write(path, contents)
+}
+
+/// Writes the save file whenever a system mutates [`SaveData`].
+fn persist_save_data(data: Res<SaveData>) {
+    if let Err(err) = save_game(&data) {
+        error!("failed to write save file: {err}");
+    }
+}
+
+/// Writes the save file once more on the frame the app is asked to quit,
+/// including by closing the window, which ends in an [`AppExit`] too. The write
+/// is synchronous, so it finishes before the process exits; a failure is only
+/// logged so it can't hold up quitting.
+fn save_on_exit(mut exit: EventReader<AppExit>, data: Res<SaveData>) {
+    if exit.read().last().is_none() {
+        return;
+    }
+    match save_game(&data) {
+        Ok(()) => info!("progress saved on exit"),
+        Err(err) => error!("failed to write save file on exit: {err}"),
+    }
+}
+
+// --- Diagnostics -----------------------------------------------------------
+
+const COLLIDER_COUNT: DiagnosticPath = DiagnosticPath::const_new("kyberchelik/colliders");
+const DYNAMIC_BODY_COUNT: DiagnosticPath = DiagnosticPath::const_new("kyberchelik/dynamic_bodies");
+const PARTICLE_COUNT: DiagnosticPath = DiagnosticPath::const_new("kyberchelik/particles");
+/// Wall time spent in the physics substep loop per frame, in milliseconds.
+const PHYSICS_SUBSTEP_TIME: DiagnosticPath =
+    DiagnosticPath::const_new("kyberchelik/physics_substep_ms");
+/// Players whose grounding missed the [`GroundingCache`] and was recomputed, per
+/// frame.
+const GROUNDING_RECOMPUTES: DiagnosticPath =
+    DiagnosticPath::const_new("kyberchelik/grounding_recomputes");
+
+/// Entity totals and physics timing for performance reports, plus a
+/// [`TuningPanel`] of the first player's movement timers. F3 toggles them;
+/// while hidden nothing is measured.
+struct StatsPlugin;
+
+impl Plugin for StatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatsOverlay>()
+            .init_resource::<SubstepTiming>()
+            .register_diagnostic(Diagnostic::new(COLLIDER_COUNT))
+            .register_diagnostic(Diagnostic::new(DYNAMIC_BODY_COUNT))
+            .register_diagnostic(Diagnostic::new(PARTICLE_COUNT))
+            .register_diagnostic(Diagnostic::new(PHYSICS_SUBSTEP_TIME).with_suffix("ms"))
+            .register_diagnostic(Diagnostic::new(GROUNDING_RECOMPUTES))
+            .add_systems(
+                PhysicsSchedule,
+                (
+                    start_substep_timer.before(PhysicsStepSet::Substeps),
+                    stop_substep_timer.after(PhysicsStepSet::Substeps),
+                )
+                    .run_if(stats_shown),
+            )
+            .add_systems(Startup, setup_tuning_panel.after(setup_camera))
+            .add_systems(
+                Update,
+                (
+                    toggle_stats_overlay,
+                    (measure_stats, log_stats, update_tuning_panel)
+                        .chain()
+                        .run_if(stats_shown),
+                )
+                    .chain(),
+            );
+    }
+}
+
+#[derive(Resource)]
+struct StatsOverlay {
+    shown: bool,
+    /// How often the overlay prints the current values.
+    log_timer: Timer,
+}
+
+impl Default for StatsOverlay {
+    fn default() -> Self {
+        Self {
+            shown: false,
+            log_timer: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+}
+
+/// Substep time accumulated since the last measurement; physics may step zero or
+/// several times per frame.
+#[derive(Resource, Default)]
+struct SubstepTiming {
+    started: Option<Instant>,
+    elapsed: Duration,
+}
+
+fn stats_shown(overlay: Res<StatsOverlay>) -> bool {
+    overlay.shown
+}
+
+fn toggle_stats_overlay(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut overlay: ResMut<StatsOverlay>,
+    mut panel: Query<&mut Visibility, With<TuningPanel>>,
+) {
+    if keyboard.just_pressed(KeyCode::F3) {
+        overlay.shown = !overlay.shown;
+        overlay.log_timer.reset();
+        for mut visibility in &mut panel {
+            *visibility = if overlay.shown {
+                Visibility::Inherited
+            } else {
+                Visibility::Hidden
+            };
+        }
+        info!("stats overlay {}", if overlay.shown { "on" } else { "off" });
+    }
+}
+
+fn start_substep_timer(mut timing: ResMut<SubstepTiming>) {
+    timing.started = Some(Instant::now());
+}
+
+fn stop_substep_timer(mut timing: ResMut<SubstepTiming>) {
+    if let Some(started) = timing.started.take() {
+        timing.elapsed += started.elapsed();
+    }
+}
+
+fn measure_stats(
+    mut diagnostics: Diagnostics,
+    mut timing: ResMut<SubstepTiming>,
+    colliders: Query<(), With<Collider>>,
+    bodies: Query<&RigidBody>,
+    particles: Query<(), With<Particle>>,
+    mut grounding: Query<&mut GroundingCache>,
+) {
+    diagnostics.add_measurement(&COLLIDER_COUNT, || colliders.iter().len() as f64);
+    diagnostics.add_measurement(&DYNAMIC_BODY_COUNT, || {
+        bodies.iter().filter(|body| body.is_dynamic()).count() as f64
+    });
+    diagnostics.add_measurement(&PARTICLE_COUNT, || particles.iter().len() as f64);
+    let elapsed = std::mem::take(&mut timing.elapsed);
+    diagnostics.add_measurement(&PHYSICS_SUBSTEP_TIME, || elapsed.as_secs_f64() * 1000.0);
+    let recomputes: u32 = grounding
+        .iter_mut()
+        .map(|mut cache| std::mem::take(&mut cache.recomputes))
+        .sum();
+    diagnostics.add_measurement(&GROUNDING_RECOMPUTES, || recomputes as f64);
+}
+
+fn log_stats(time: Res<Time>, mut overlay: ResMut<StatsOverlay>, store: Res<DiagnosticsStore>) {
+    if !overlay.log_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let value = |path: &DiagnosticPath| {
+        store
+            .get(path)
+            .and_then(|diagnostic| diagnostic.smoothed())
+            .unwrap_or(0.0)
+    };
+    info!(
+        "colliders {:.0}, dynamic bodies {:.0}, particles {:.0}, physics substeps {:.2}ms, \
+         grounding recomputes {:.2}/frame",
+        value(&COLLIDER_COUNT),
+        value(&DYNAMIC_BODY_COUNT),
+        value(&PARTICLE_COUNT),
+        value(&PHYSICS_SUBSTEP_TIME),
+        value(&GROUNDING_RECOMPUTES),
+    );
+}
+
+const TUNING_BAR_SIZE: Vec2 = Vec2::new(96.0, 6.0);
+const TUNING_LIGHT_SIZE: f32 = 10.0;
+const TUNING_MARGIN: f32 = 16.0;
+const TUNING_LIGHT_ON: Color = Color::srgb(0.4, 1.0, 0.5);
+const TUNING_LIGHT_OFF: Color = Color::srgba(0.4, 0.4, 0.45, 0.6);
+
+/// Movement-timing readout in the bottom-left corner while the stats overlay is
+/// on, for the first player. There is no text, so the rows go by position and
+/// colour; see [`TuningBar`] and [`ContactLight`].
+#[derive(Component)]
+struct TuningPanel;
+
+/// One bar of the [`TuningPanel`], listed top to bottom. Each is full while its
+/// window is fully open and empties as it runs out.
+#[derive(Component, Clone, Copy)]
+enum TuningBar {
+    /// Coyote time left after leaving a ledge; empty without `generous_jump`.
+    Coyote,
+    /// Time left for a buffered jump press; empty without `generous_jump`.
+    Buffer,
+    /// Time left of the current burst dash.
+    Dash,
+    /// Dash cooldown left.
+    Cooldown,
+}
+
+impl TuningBar {
+    const ALL: [TuningBar; 4] = [
+        TuningBar::Coyote,
+        TuningBar::Buffer,
+        TuningBar::Dash,
+        TuningBar::Cooldown,
+    ];
+
+    fn color(self) -> Color {
+        match self {
+            TuningBar::Coyote => Color::srgb(0.95, 0.75, 0.3),
+            TuningBar::Buffer => Color::srgb(0.45, 0.85, 1.0),
+            TuningBar::Dash => Color::srgb(1.0, 0.45, 0.7),
+            TuningBar::Cooldown => Color::srgb(0.7, 0.7, 0.75),
+        }
+    }
+}
+
+/// A square under the bars that lights up while the player touches a tile on
+/// that side of [`ContactSides`], left to right in the order listed.
+#[derive(Component, Clone, Copy)]
+enum ContactLight {
+    Left,
+    Floor,
+    Ceiling,
+    Right,
+}
+
+impl ContactLight {
+    const ALL: [ContactLight; 4] = [
+        ContactLight::Left,
+        ContactLight::Floor,
+        ContactLight::Ceiling,
+        ContactLight::Right,
+    ];
+
+    fn lit(self, sides: &ContactSides) -> bool {
+        match self {
+            ContactLight::Left => sides.left,
+            ContactLight::Floor => sides.floor,
+            ContactLight::Ceiling => sides.ceiling,
+            ContactLight::Right => sides.right,
+        }
+    }
+}
+
+fn setup_tuning_panel(mut commands: Commands, camera: Query<Entity, With<MainCamera>>) {
+    let corner = Vec2::new(-WINDOW_WIDTH, -WINDOW_HEIGHT) * 0.5 + TUNING_MARGIN;
+    let panel = commands
+        .spawn((
+            TuningPanel,
+            SpatialBundle {
+                transform: Transform::from_xyz(
+                    corner.x,
+                    corner.y,
+                    z_layers::camera_local(z_layers::UI_WORLD + 2.0),
+                ),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            let row = TUNING_BAR_SIZE.y + 4.0;
+            let bars_bottom = TUNING_LIGHT_SIZE + 8.0;
+            for (i, bar) in TuningBar::ALL.into_iter().enumerate() {
+                let y = bars_bottom + (TuningBar::ALL.len() - 1 - i) as f32 * row;
+                parent.spawn((
+                    bar,
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: bar.color(),
+                            custom_size: Some(TUNING_BAR_SIZE),
+                            anchor: Anchor::BottomLeft,
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(0.0, y, 0.0),
+                        ..default()
+                    },
+                ));
+            }
+            for (i, light) in ContactLight::ALL.into_iter().enumerate() {
+                parent.spawn((
+                    light,
+                    SpriteBundle {
+                        sprite: Sprite {
+                            color: TUNING_LIGHT_OFF,
+                            custom_size: Some(Vec2::splat(TUNING_LIGHT_SIZE)),
+                            anchor: Anchor::BottomLeft,
+                            ..default()
+                        },
+                        transform: Transform::from_xyz(
+                            i as f32 * (TUNING_LIGHT_SIZE + 4.0),
+                            0.0,
+                            0.0,
+                        ),
+                        ..default()
+                    },
+                ));
+            }
+        })
+        .id();
+    commands.entity(camera.single()).add_child(panel);
+}
+
+/// Share of a `window` of seconds still left after `elapsed`.
+fn window_left(elapsed: f32, window: f32) -> f32 {
+    if window > 0.0 {
+        (1.0 - elapsed / window).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+fn update_tuning_panel(
+    timing: Res<TimingConfig>,
+    accessibility: Res<AccessibilityConfig>,
+    players: Query<(&JumpForgiveness, &DashTimers, &PlayerState, &ContactSides), With<Player>>,
+    mut bars: Query<(&TuningBar, &mut Sprite), Without<ContactLight>>,
+    mut lights: Query<(&ContactLight, &mut Sprite), Without<TuningBar>>,
+) {
+    let Some((forgiveness, dash, state, sides)) = players.iter().next() else {
+        return;
+    };
+
+    for (bar, mut sprite) in &mut bars {
+        let fill = match bar {
+            TuningBar::Coyote if accessibility.generous_jump => {
+                window_left(forgiveness.air_time, timing.coyote_time)
+            }
+            TuningBar::Buffer if accessibility.generous_jump => {
+                window_left(forgiveness.since_press, timing.jump_buffer)
+            }
+            TuningBar::Coyote | TuningBar::Buffer => 0.0,
+            TuningBar::Dash if *state == PlayerState::Dashing => dash.duration.fraction_remaining(),
+            TuningBar::Dash => 0.0,
+            TuningBar::Cooldown => dash.cooldown.fraction_remaining(),
+        };
+        sprite.custom_size = Some(TUNING_BAR_SIZE * Vec2::new(fill, 1.0));
+    }
+    for (light, mut sprite) in &mut lights {
+        sprite.color = if light.lit(sides) {
+            TUNING_LIGHT_ON
+        } else {
+            TUNING_LIGHT_OFF
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::keys::Door;
+    use crate::testing::{press, step, test_app};
+
+    fn tap(app: &mut App, key: KeyCode) {
+        press(app, key);
+        step(app, 1);
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .release(key);
+    }
+
+    fn player_motion(app: &mut App) -> (Vec2, Vec2) {
+        let world = app.world_mut();
+        let (position, velocity) = world
+            .query_filtered::<(&Position, &LinearVelocity), With<Player>>()
+            .single(world);
+        (position.0, velocity.0)
+    }
+
+    #[test]
+    fn the_view_is_kept_inside_the_level_on_both_axes() {
+        let bounds = Rect::new(-500.0, -300.0, 500.0, 300.0);
+        let half = Vec2::new(200.0, 100.0);
+        let clamp = |target| clamp_view_to_level(target, bounds, half);
+
+        assert_eq!(clamp(Vec2::new(10.0, -20.0)), Vec2::new(10.0, -20.0));
+        assert_eq!(clamp(Vec2::new(-480.0, 0.0)), Vec2::new(-300.0, 0.0));
+        assert_eq!(clamp(Vec2::new(480.0, 290.0)), Vec2::new(300.0, 200.0));
+        assert_eq!(clamp(Vec2::new(0.0, -1000.0)), Vec2::new(0.0, -200.0));
+        // A level narrower than the view is centred instead.
+        let narrow = Rect::new(0.0, -300.0, 100.0, 300.0);
+        assert_eq!(
+            clamp_view_to_level(Vec2::new(90.0, 0.0), narrow, half),
+            Vec2::new(50.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn pausing_freezes_the_player_and_resumes_without_catching_up() {
+        let mut app = test_app(testing::TEST_ROOM);
+        app.add_plugins((bevy::state::app::StatesPlugin, PausePlugin))
+            .add_event::<WindowFocused>()
+            .insert_resource(Time::new_with(Physics::fixed_hz(PHYSICS_HZ)));
+        step(&mut app, 60);
+
+        // Pause mid-jump, where gravity would change the velocity every step.
+        tap(&mut app, KeyCode::Space);
+        step(&mut app, 5);
+        // The state changes at the start of the next frame.
+        tap(&mut app, KeyCode::Escape);
+        step(&mut app, 1);
+        let paused = player_motion(&mut app);
+        assert!(paused.1.y > 0.0);
+
+        step(&mut app, 120);
+        assert_eq!(player_motion(&mut app), paused);
+        assert!(app.world().resource::<Time<Physics>>().is_paused());
+
+        tap(&mut app, KeyCode::Escape);
+        assert_eq!(player_motion(&mut app), paused);
+        // Time piled up for fixed steps must not be spent on the frame play resumes.
+        if let TimestepMode::Fixed { overstep, .. } = app
+            .world_mut()
+            .resource_mut::<Time<Physics>>()
+            .timestep_mode_mut()
+        {
+            *overstep = Duration::from_secs(2);
+        }
+        step(&mut app, 1);
+        let (position, velocity) = player_motion(&mut app);
+        let one_step = paused.1.length() / PHYSICS_HZ as f32;
+        assert!(
+            position.distance(paused.0) <= one_step * 1.5,
+            "jumped {} on resume",
+            position.distance(paused.0)
+        );
+        assert!((velocity - paused.1).length() <= GRAVITY / PHYSICS_HZ as f32 * 2.0);
+    }
+
+    /// An app on the game's fixed-step physics clock whose frames each take
+    /// `frame_time`, like a machine struggling to keep up.
+    fn slow_frame_app(map: &[&str], frame_time: f32) -> App {
+        let mut app = test_app(map);
+        app.insert_resource(Time::new_with(Physics::fixed_hz(PHYSICS_HZ)));
+        step(&mut app, 60);
+        app.insert_resource(bevy::time::TimeUpdateStrategy::ManualDuration(
+            Duration::from_secs_f32(frame_time),
+        ));
+        app
+    }
+
+    #[test]
+    fn a_dash_on_slow_frames_stops_at_a_thin_door() {
+        let mut app = slow_frame_app(
+            &["##########", "#........#", "#P..D....#", "##########"],
+            0.1,
+        );
+        let world = app.world_mut();
+        let door = world
+            .query_filtered::<&Transform, With<Door>>()
+            .single(world)
+            .translation
+            .x;
+
+        tap(&mut app, KeyCode::ShiftLeft);
+        step(&mut app, 10);
+        let (position, _) = player_motion(&mut app);
+        assert!(position.x < door, "dashed through to {}", position.x);
+    }
+
+    #[test]
+    fn a_fast_fall_on_slow_frames_lands_on_a_one_tile_floor() {
+        let mut app = slow_frame_app(
+            &[
+                "#######", "#.....#", "#.....#", "#.....#", "#P....#", "#######", "#.....#",
+                "#######",
+            ],
+            0.1,
+        );
+        let world = app.world_mut();
+        let (floor, high) = {
+            let level = world.resource::<LevelData>();
+            let config = world.resource::<LevelConfig>();
+            (
+                level.tile_center(1, 5, config).y,
+                level.tile_center(1, 1, config),
+            )
+        };
+        let (mut position, mut velocity) = world
+            .query_filtered::<(&mut Position, &mut LinearVelocity), With<Player>>()
+            .single_mut(world);
+        position.0 = high;
+        velocity.0 = Vec2::new(0.0, -3000.0);
+
+        step(&mut app, 10);
+        let (position, _) = player_motion(&mut app);
+        assert!(position.y > floor, "fell through to {}", position.y);
+    }
+
+    #[test]
+    fn the_minimap_resizes_for_a_reloaded_level() {
+        let mut app = test_app(testing::TEST_ROOM);
+        app.add_systems(Startup, setup_camera)
+            .add_plugins(MinimapPlugin);
+        step(&mut app, 1);
+
+        let bigger = level::load_level(&vec!["#".repeat(40); 30]);
+        app.insert_resource(bigger.clone());
+        app.world_mut().send_event(LevelReloaded);
+        step(&mut app, 1);
+
+        let world = app.world_mut();
+        let (sprite, transform) = world
+            .query_filtered::<(&Sprite, &Transform), With<Minimap>>()
+            .single(world);
+        let size = minimap_size(&bigger);
+        assert_eq!(sprite.custom_size, Some(size));
+        assert_eq!(transform.translation.truncate(), minimap_corner(size));
+    }
+}
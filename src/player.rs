@@ -0,0 +1,2774 @@
+//! The player character: input, movement, state and animation.
+
+use bevy::input::gamepad::GamepadConnectionEvent;
+use bevy::prelude::*;
+use bevy::render::render_asset::RenderAssetUsages;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::sprite::{Anchor, SpriteBundle, TextureAtlas, TextureAtlasLayout};
+use bevy_xpbd_2d::prelude::*;
+use bevy_xpbd_2d::{PhysicsSchedule, PhysicsStepSet};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::TAU;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::level::{
+    quick_restart, setup_level, LevelConfig, LevelData, LevelRestarted, SpawnPoint,
+};
+use crate::{
+    z_layers, GameLayer, GameplaySet, GRAVITY, GROUND_TOLERANCE, PLAYER_COLLIDER_SIZE,
+    PLAYER_HALF_HEIGHT, PLAYER_LAYERS, PLAYER_SIZE,
+};
+
+const DASH_READY_FLASH_TIME: f32 = 0.1;
+const DASH_READY_FLASH_COLOR: Color = Color::srgb(1.0, 1.0, 0.45);
+const SECOND_PLAYER_TINT: Color = Color::srgb(0.6, 1.0, 0.65);
+
+/// Spawns the players and runs their input, movement and animation.
+pub struct PlayerPlugin;
+
+impl Plugin for PlayerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayerConfig>()
+            .init_resource::<AccessibilityConfig>()
+            .init_resource::<AssistMode>()
+            .init_resource::<AnimationConfig>()
+            .init_resource::<RubberMode>()
+            .init_resource::<FacingLock>()
+            .init_resource::<GravityFlip>()
+            .init_resource::<DashTints>()
+            .insert_resource(load_timing_config())
+            .init_resource::<TimingFile>()
+            .add_event::<GroundedChanged>()
+            .add_event::<Landed>()
+            .add_event::<WarpPlayers>()
+            .add_systems(
+                Startup,
+                (setup_player_assets, spawn_player.after(setup_level)).chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    toggle_rubber_mode,
+                    toggle_facing_lock,
+                    toggle_gravity_flip,
+                    apply_rubber_mode,
+                    apply_gravity_flip,
+                    player_input,
+                    flash_dash_ready,
+                    correct_ceiling_corners,
+                    step_up_ledges,
+                    cap_horizontal_speed,
+                    update_player_state,
+                    animate_player,
+                    apply_ground_snap,
+                )
+                    .chain()
+                    .in_set(GameplaySet),
+            )
+            .add_systems(Update, handle_gamepad_connections)
+            .add_systems(Update, reload_timing_config)
+            .add_systems(
+                Update,
+                reset_players.after(quick_restart).in_set(GameplaySet),
+            );
+        app.add_systems(
+            PhysicsSchedule,
+            record_previous_positions.before(PhysicsStepSet::BroadPhase),
+        )
+        .add_systems(
+            PostUpdate,
+            (interpolate_player_positions, update_energy_bars)
+                .chain()
+                .after(PhysicsSet::Sync)
+                .before(TransformSystem::TransformPropagate),
+        );
+        #[cfg(feature = "debug")]
+        app.add_plugins(NoClipPlugin);
+    }
+}
+
+#[derive(Resource)]
+pub struct PlayerConfig {
+    pub move_speed: f32,
+    pub jump_speed: f32,
+    pub dash_speed: f32,
+    /// How much of the steering is kept in the air: 0 keeps the take-off
+    /// momentum untouched, 1 steers as freely as on the ground.
+    pub air_control: f32,
+    /// Gravity multipliers while moving up and while moving down.
+    pub rise_gravity_scale: f32,
+    pub fall_gravity_scale: f32,
+    /// Shift of the sprite relative to the collider, so the feet line up with the art.
+    pub sprite_offset: Vec2,
+    /// Furthest the player is pushed sideways to slip past a ceiling corner, in pixels.
+    pub corner_correction: f32,
+    /// Tallest lip a grounded player walks up onto instead of stopping, in pixels.
+    pub step_height: f32,
+    /// Fraction of the dash speed kept when a dash is cancelled into a jump.
+    pub dash_jump_retain: f32,
+    /// Restitution of the player while [`RubberMode`] is on.
+    pub rubber_restitution: f32,
+    /// Spawn a second player on the other half of the keyboard; enabled with `--coop`.
+    pub coop: bool,
+    /// How a dash ends; `--sustained-dash` picks [`DashMode::Sustained`].
+    pub dash_mode: DashMode,
+    /// Share of a full [`EnergyMeter`] a sustained dash burns per second.
+    pub dash_energy_drain: f32,
+    /// Share of a full meter refilled per second while not dashing.
+    pub dash_energy_regen: f32,
+    /// Fastest the player moves sideways outside a dash, however the speed was
+    /// built up, in pixels per second.
+    pub max_horizontal_speed: f32,
+    /// The same cap while dashing.
+    pub max_dash_speed: f32,
+    /// Holding grab against a wall in mid-air clings to it.
+    pub wall_grab: bool,
+    /// Speed of climbing up or down a grabbed wall, in pixels per second.
+    pub grab_climb_speed: f32,
+    /// Share of full [`Stamina`] a wall grab burns per second while holding
+    /// still, and the extra share burned while climbing.
+    pub grab_stamina_drain: f32,
+    pub grab_climb_drain: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DashMode {
+    /// Every dash lasts `dash_duration`.
+    Burst,
+    /// The dash lasts as long as the button is held and energy is left.
+    Sustained,
+}
+
+impl Default for PlayerConfig {
+    fn default() -> Self {
+        Self {
+            move_speed: 360.0,
+            jump_speed: 640.0,
+            dash_speed: 820.0,
+            air_control: 0.6,
+            rise_gravity_scale: 0.9,
+            fall_gravity_scale: 1.6,
+            sprite_offset: Vec2::ZERO,
+            corner_correction: 10.0,
+            step_height: 8.0,
+            dash_jump_retain: 0.75,
+            rubber_restitution: 0.8,
+            coop: false,
+            dash_mode: DashMode::Burst,
+            dash_energy_drain: 1.25,
+            dash_energy_regen: 0.5,
+            max_horizontal_speed: 720.0,
+            max_dash_speed: 960.0,
+            wall_grab: true,
+            grab_climb_speed: 120.0,
+            grab_stamina_drain: 0.25,
+            grab_climb_drain: 0.35,
+        }
+    }
+}
+
+/// Chaos mode where the player bounces off floors and walls. Toggled with B.
+#[derive(Resource, Default, Debug)]
+pub(crate) struct RubberMode(bool);
+
+/// While on, the players keep facing whichever way they faced when it was
+/// switched on; running and dashing still follow the input. Toggled with L.
+#[derive(Resource, Default, Debug)]
+struct FacingLock(bool);
+
+/// Turns gravity upside down, so the players fall onto the ceiling and jump
+/// downwards. Toggled with G.
+///
+/// Movement code works with [`GravityFlip::up`], the sign of "up" on the y axis,
+/// rather than the flag itself. Sideways gravity would replace that sign with a
+/// rotation.
+#[derive(Resource, Default, Debug)]
+pub(crate) struct GravityFlip(bool);
+
+impl GravityFlip {
+    /// 1 with normal gravity and -1 when flipped.
+    pub(crate) fn up(&self) -> f32 {
+        if self.0 {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    fn up_dir(&self) -> Dir2 {
+        if self.0 {
+            Dir2::NEG_Y
+        } else {
+            Dir2::Y
+        }
+    }
+}
+
+/// Below this vertical speed a bouncing player is considered to have come to rest.
+const BOUNCE_REST_SPEED: f32 = 60.0;
+
+/// Every game-feel timer in one place, in seconds. Read from `timing.ron` in the
+/// data directory when present, and reloaded while the game runs whenever that
+/// file changes.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TimingConfig {
+    pub dash_duration: f32,
+    pub dash_cooldown: f32,
+    /// How long after leaving a ledge a jump still counts, with `generous_jump`.
+    pub coyote_time: f32,
+    /// How long before landing a jump press is remembered, with `generous_jump`.
+    pub jump_buffer: f32,
+    /// How long a keyboard direction takes to go from 0 to full, with
+    /// `smooth_keyboard`.
+    pub input_ramp: f32,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            dash_duration: 0.18,
+            dash_cooldown: 0.35,
+            coyote_time: 0.15,
+            jump_buffer: 0.15,
+            input_ramp: 0.1,
+        }
+    }
+}
+
+fn timing_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("kyberchelik").join("timing.ron"))
+}
+
+fn load_timing_config() -> TimingConfig {
+    let Some(path) = timing_path() else {
+        return TimingConfig::default();
+    };
+    // A missing file is the normal case; only a broken one is worth a warning.
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return TimingConfig::default();
+    };
+
+    ron::from_str::<TimingConfig>(&contents)
+        .map(TimingConfig::validate_and_clamp)
+        .unwrap_or_else(|err| {
+            warn!(
+                "timing file {} is invalid: {err}, using defaults",
+                path.display()
+            );
+            TimingConfig::default()
+        })
+}
+
+impl TimingConfig {
+    /// Keeps a hand-edited file from breaking the game: non-finite values fall
+    /// back to the default and the rest are clamped to a sane range, with a
+    /// warning for each value changed. A zero dash duration, for one, would
+    /// end every dash on the frame it starts.
+    fn validate_and_clamp(mut self) -> Self {
+        let defaults = Self::default();
+        sanitize_timing(
+            "dash_duration",
+            &mut self.dash_duration,
+            defaults.dash_duration,
+            0.02..=2.0,
+        );
+        sanitize_timing(
+            "dash_cooldown",
+            &mut self.dash_cooldown,
+            defaults.dash_cooldown,
+            0.0..=5.0,
+        );
+        sanitize_timing(
+            "coyote_time",
+            &mut self.coyote_time,
+            defaults.coyote_time,
+            0.0..=1.0,
+        );
+        sanitize_timing(
+            "jump_buffer",
+            &mut self.jump_buffer,
+            defaults.jump_buffer,
+            0.0..=1.0,
+        );
+        sanitize_timing(
+            "input_ramp",
+            &mut self.input_ramp,
+            defaults.input_ramp,
+            0.0..=1.0,
+        );
+        self
+    }
+}
+
+fn sanitize_timing(name: &str, value: &mut f32, default: f32, range: RangeInclusive<f32>) {
+    if !value.is_finite() {
+        warn!("timing {name} is {value}, using the default {default}");
+        *value = default;
+    } else if !range.contains(value) {
+        let clamped = value.clamp(*range.start(), *range.end());
+        warn!("timing {name} {value} is outside {range:?}, using {clamped}");
+        *value = clamped;
+    }
+}
+
+/// A file whose modification time is polled once a second to notice edits.
+pub(crate) struct WatchedFile {
+    pub(crate) path: Option<PathBuf>,
+    pub(crate) modified: Option<SystemTime>,
+    pub(crate) poll: Timer,
+}
+
+impl WatchedFile {
+    pub(crate) fn new(path: Option<PathBuf>) -> Self {
+        Self {
+            modified: Self::modified_time(&path),
+            path,
+            poll: Timer::from_seconds(1.0, TimerMode::Repeating),
+        }
+    }
+
+    fn modified_time(path: &Option<PathBuf>) -> Option<SystemTime> {
+        fs::metadata(path.as_ref()?).ok()?.modified().ok()
+    }
+
+    /// Whether the file was created, changed or removed since the last poll.
+    pub(crate) fn changed(&mut self, delta: Duration) -> bool {
+        if !self.poll.tick(delta).just_finished() {
+            return false;
+        }
+        let modified = Self::modified_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+#[derive(Resource)]
+struct TimingFile(WatchedFile);
+
+impl Default for TimingFile {
+    fn default() -> Self {
+        Self(WatchedFile::new(timing_path()))
+    }
+}
+
+fn reload_timing_config(
+    time: Res<Time<Real>>,
+    mut file: ResMut<TimingFile>,
+    mut timing: ResMut<TimingConfig>,
+    mut dash_timers: Query<&mut DashTimers>,
+) {
+    if !file.0.changed(time.delta()) {
+        return;
+    }
+
+    *timing = load_timing_config();
+    info!("reloaded timing config: {:?}", *timing);
+    for mut dash_timers in &mut dash_timers {
+        dash_timers
+            .duration
+            .set_duration(Duration::from_secs_f32(timing.dash_duration));
+        dash_timers
+            .cooldown
+            .set_duration(Duration::from_secs_f32(timing.dash_cooldown));
+    }
+}
+
+/// Optional assists. The gameplay assists are off by default so normal play is
+/// unaffected.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub(crate) struct AccessibilityConfig {
+    /// Holding jump keeps hopping every time the player lands.
+    pub(crate) auto_hop: bool,
+    /// Accepts jumps pressed slightly before landing or after leaving a ledge.
+    pub(crate) generous_jump: bool,
+    /// Pause when the window loses focus, e.g. on alt-tab.
+    pub(crate) pause_on_focus_loss: bool,
+    /// Eases keyboard movement in and out over `input_ramp` seconds, for a
+    /// gentler, more stick-like feel.
+    pub(crate) smooth_keyboard: bool,
+}
+
+impl Default for AccessibilityConfig {
+    fn default() -> Self {
+        Self {
+            auto_hop: false,
+            generous_jump: false,
+            pause_on_focus_loss: true,
+            smooth_keyboard: false,
+        }
+    }
+}
+
+/// Assists that make the game easier, all switched on or off together with F9.
+/// Each one can still be turned off on its own in the save file.
+#[derive(Resource, Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub(crate) struct AssistMode {
+    pub(crate) enabled: bool,
+    /// Game speed while enabled, relative to normal.
+    pub(crate) game_speed: f32,
+    /// Jump again in mid-air, as often as wanted.
+    pub(crate) unlimited_jumps: bool,
+    /// Dash without waiting for the cooldown or, in sustained mode, using energy.
+    pub(crate) infinite_dashes: bool,
+}
+
+impl Default for AssistMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            game_speed: 0.75,
+            unlimited_jumps: true,
+            infinite_dashes: true,
+        }
+    }
+}
+
+impl AssistMode {
+    /// Speed the game should run at, clamped so a bad save can't stop or
+    /// speed up time.
+    pub(crate) fn speed(&self) -> f32 {
+        if self.enabled {
+            self.game_speed.clamp(0.25, 1.0)
+        } else {
+            1.0
+        }
+    }
+
+    pub(crate) fn jumps(&self) -> bool {
+        self.enabled && self.unlimited_jumps
+    }
+
+    pub(crate) fn dashes(&self) -> bool {
+        self.enabled && self.infinite_dashes
+    }
+
+    /// Whether any assist currently changes the game.
+    pub(crate) fn is_active(&self) -> bool {
+        self.speed() < 1.0 || self.jumps() || self.dashes()
+    }
+}
+
+/// Atlas frames shown for one animation state, each with its own duration in
+/// seconds.
+struct AnimationClip {
+    frames: Vec<(usize, f32)>,
+    /// Start over after the last frame; otherwise hold it.
+    looping: bool,
+    tint: Color,
+}
+
+impl AnimationClip {
+    fn still(frame: usize) -> Self {
+        Self {
+            frames: vec![(frame, f32::INFINITY)],
+            looping: false,
+            tint: Color::WHITE,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct AnimationConfig {
+    standing: AnimationClip,
+    jumping: AnimationClip,
+    falling: AnimationClip,
+    dashing: AnimationClip,
+    grabbing: AnimationClip,
+    /// Played instead of `standing` while moving sideways on the ground, faster
+    /// or slower with the speed relative to `move_speed`.
+    running: AnimationClip,
+    /// Bounds of the running playback rate, so a slow creep doesn't stall the
+    /// cycle and a dash-boosted run doesn't blur it.
+    run_rate: (f32, f32),
+    /// Played after standing still for `idle_delay` seconds.
+    idle: AnimationClip,
+    idle_delay: f32,
+    /// Breaths per second and relative scale change of the idle breathing.
+    breathing_rate: f32,
+    breathing_amplitude: f32,
+}
+
+impl AnimationConfig {
+    /// Playback rate of the running clip at `speed`, relative to a run at
+    /// `move_speed` and clamped to `run_rate`.
+    fn run_playback_rate(&self, speed: f32, move_speed: f32) -> f32 {
+        let (min, max) = self.run_rate;
+        (speed.abs() / move_speed).clamp(min, max)
+    }
+
+    fn clip(&self, state: PlayerState) -> &AnimationClip {
+        match state {
+            PlayerState::Standing => &self.standing,
+            PlayerState::Jumping => &self.jumping,
+            PlayerState::Falling => &self.falling,
+            PlayerState::Dashing => &self.dashing,
+            PlayerState::Grabbing => &self.grabbing,
+        }
+    }
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            standing: AnimationClip::still(0),
+            jumping: AnimationClip::still(1),
+            falling: AnimationClip::still(2),
+            // The dash tint comes from `DashTints`.
+            dashing: AnimationClip {
+                frames: vec![(2, 0.14), (3, 0.14)],
+                looping: true,
+                ..AnimationClip::still(2)
+            },
+            // The placeholder atlas has no climbing frames.
+            grabbing: AnimationClip::still(1),
+            running: AnimationClip {
+                frames: vec![(4, 0.12), (5, 0.12)],
+                looping: true,
+                ..AnimationClip::still(4)
+            },
+            run_rate: (0.25, 2.0),
+            idle: AnimationClip::still(0),
+            idle_delay: 1.0,
+            breathing_rate: 0.5,
+            breathing_amplitude: 0.04,
+        }
+    }
+}
+
+/// Marks each player entity; co-op spawns two.
+#[derive(Component)]
+pub struct Player;
+
+#[derive(Component, Copy, Clone, Eq, PartialEq, Debug, Default)]
+pub enum PlayerState {
+    #[default]
+    Standing,
+    Jumping,
+    Falling,
+    Dashing,
+    /// Clinging to a wall; see [`PlayerConfig::wall_grab`].
+    Grabbing,
+}
+
+/// What made a [`PlayerState`] change, as reported by the `trace_movement` log.
+#[derive(Clone, Copy, Debug)]
+enum StateCause {
+    /// A jump or dash press, or letting go of a sustained dash.
+    Input,
+    /// Landing on or leaving the floor.
+    Grounding,
+    /// A jump reaching its apex.
+    Apex,
+    /// A rubber-mode bounce off the floor.
+    Bounce,
+    /// A burst dash running out its duration.
+    Timer,
+    /// A sustained dash running out of energy.
+    Energy,
+    /// A wall grab running out of stamina.
+    Stamina,
+    /// Losing contact with the grabbed wall.
+    Wall,
+    /// A level restart or warp.
+    Reset,
+}
+
+/// Moves a player to `next`. Every state change goes through here so that, with
+/// the `trace_movement` feature, each one is logged with its cause, the time
+/// and the velocity; without the feature this is a plain assignment.
+#[cfg_attr(not(feature = "trace_movement"), allow(unused_variables))]
+fn set_state(
+    state: &mut PlayerState,
+    next: PlayerState,
+    cause: StateCause,
+    entity: Entity,
+    time: &Time,
+    velocity: Vec2,
+) {
+    #[cfg(feature = "trace_movement")]
+    if *state != next {
+        info!(
+            target: "movement",
+            ?entity,
+            from = ?*state,
+            to = ?next,
+            ?cause,
+            time = time.elapsed_seconds_f64(),
+            velocity_x = velocity.x,
+            velocity_y = velocity.y,
+            "player state changed"
+        );
+    }
+    *state = next;
+}
+
+/// Horizontal direction the player looks in: -1 for left, 1 for right.
+#[derive(Component, Debug)]
+pub struct Facing(pub f32);
+
+/// Position in the current [`AnimationClip`]: frame number and seconds spent on it.
+#[derive(Component, Default)]
+struct AnimationPlayback {
+    frame: usize,
+    elapsed: f32,
+}
+
+/// Seconds the player has been standing still.
+#[derive(Component, Default)]
+struct IdleTime(f32);
+
+#[derive(Component)]
+pub(crate) struct DashTimers {
+    pub(crate) duration: Timer,
+    pub(crate) cooldown: Timer,
+    /// Sprite tint for the current dash, picked from [`DashTints`] when it starts.
+    tint: Color,
+    /// Horizontal direction of the current dash, -1 or 1. Follows the input,
+    /// not [`Facing`], so it still works with [`FacingLock`] on.
+    direction: f32,
+}
+
+/// Fuel for sustained dashes, from 0 (empty) to 1 (full). Only drains with
+/// [`DashMode::Sustained`].
+#[derive(Component)]
+struct EnergyMeter(f32);
+
+/// What a wall grab burns, from 0 (empty) to 1 (full). Refills on landing.
+#[derive(Component)]
+struct Stamina(f32);
+
+/// Sideways speed into a grabbed wall, so the contact that keeps the grab going
+/// isn't lost to rounding.
+const GRAB_WALL_PUSH: f32 = 40.0;
+
+/// Bar above a player showing their [`Stamina`] while grabbing a wall, and
+/// otherwise their [`EnergyMeter`] in sustained dash mode.
+#[derive(Component)]
+struct EnergyBar;
+
+const ENERGY_BAR_COLOR: Color = Color::srgb(0.45, 0.85, 1.0);
+const STAMINA_BAR_COLOR: Color = Color::srgb(1.0, 0.7, 0.3);
+
+const ENERGY_BAR_SIZE: Vec2 = Vec2::new(PLAYER_SIZE.x, 4.0);
+/// Where the bar's left end sits relative to the player's center.
+const ENERGY_BAR_OFFSET: Vec2 = Vec2::new(-PLAYER_SIZE.x * 0.5, PLAYER_SIZE.y * 0.5 + 6.0);
+
+/// Brief tint on the player the moment their dash cooldown runs out.
+#[derive(Component)]
+struct DashReadyFlash(Timer);
+
+/// Runs a timer to its end, e.g. so a cooldown starts out already available. The
+/// second tick clears `just_finished`, so this doesn't read as the timer expiring.
+fn finish_timer(timer: &mut Timer) {
+    let duration = timer.duration();
+    timer.tick(duration);
+    timer.tick(Duration::ZERO);
+}
+
+/// Base colour of a player, multiplied into every state and dash tint so a
+/// palette swap survives animation changes. Change it to recolour a player.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct PlayerTint(pub Color);
+
+impl Default for PlayerTint {
+    fn default() -> Self {
+        Self(Color::WHITE)
+    }
+}
+
+/// Component-wise product of two colours in linear space, alpha included.
+fn multiply_colors(a: Color, b: Color) -> Color {
+    LinearRgba::from_vec4(a.to_linear().to_vec4() * b.to_linear().to_vec4()).into()
+}
+
+/// Sprite tint per dash direction, so the direction reads at a glance. Dashes
+/// are only horizontal so far; up and down tints belong here once they aren't.
+#[derive(Resource)]
+struct DashTints {
+    horizontal: Color,
+}
+
+impl Default for DashTints {
+    fn default() -> Self {
+        Self {
+            horizontal: Color::srgb(1.0, 0.8, 0.8),
+        }
+    }
+}
+
+/// Horizontal speed carried from a dash-cancel jump until landing or steering against it.
+#[derive(Component, Default)]
+struct DashMomentum(f32);
+
+#[derive(Component)]
+struct PlayerAnimation;
+
+#[derive(Component)]
+pub struct Grounded(pub bool);
+
+/// Position and vertical speed the last time grounding was fully recomputed.
+///
+/// While the player stands still vertically on the same contacts, the answer can't
+/// change, so `update_player_state` skips the per-contact AABB lookups. Standing or
+/// running on flat ground this cuts the recompute from every frame to only the
+/// frames where a contact starts or ends, which is where the cost grows with the
+/// number of touching solids, tiles and closed doors alike. The F3 stats report how
+/// often it misses.
+#[derive(Component, Default)]
+pub(crate) struct GroundingCache {
+    y: f32,
+    velocity_y: f32,
+    /// Full recomputes since the stats last read this.
+    pub(crate) recomputes: u32,
+}
+
+/// Vertical movement, in pixels, and change of vertical speed, in pixels per
+/// second, that [`GroundingCache`] ignores as solver jitter on a resting body.
+/// Gravity alone changes the speed by 25 per step, so a real jump or fall is
+/// never missed.
+const GROUNDING_CACHE_Y_EPSILON: f32 = 0.01;
+const GROUNDING_CACHE_VELOCITY_EPSILON: f32 = 1.0;
+
+impl GroundingCache {
+    /// Whether the cached result may be stale, i.e. the player moved or was pushed
+    /// vertically since it was taken.
+    fn is_dirty(&self, y: f32, velocity_y: f32) -> bool {
+        (y - self.y).abs() > GROUNDING_CACHE_Y_EPSILON
+            || (velocity_y - self.velocity_y).abs() > GROUNDING_CACHE_VELOCITY_EPSILON
+    }
+}
+
+/// Which sides of the player touch a tile, refreshed by `update_player_state`
+/// together with [`Grounded`]. Read this instead of walking the contacts again.
+/// `floor` and `ceiling` follow gravity, so they swap while it is flipped.
+#[derive(Component, Default, Clone, Copy, Debug)]
+pub struct ContactSides {
+    pub left: bool,
+    pub right: bool,
+    pub ceiling: bool,
+    pub floor: bool,
+}
+
+/// Sent by `update_player_state` on the frame `Grounded` flips.
+#[derive(Event)]
+pub struct GroundedChanged {
+    pub entity: Entity,
+    pub grounded: bool,
+}
+
+/// Sent alongside [`GroundedChanged`] when a player touches down, with how fast
+/// they were falling just before, in pixels per second.
+#[derive(Event)]
+pub struct Landed {
+    pub entity: Entity,
+    pub speed: f32,
+}
+
+/// Downward speed on the player's last frame in the air, reported by [`Landed`].
+/// The contact has already stopped the fall by the frame grounding notices it.
+#[derive(Component, Default)]
+pub(crate) struct FallSpeed(f32);
+
+/// Seconds since the player was last grounded and since jump was last pressed.
+#[derive(Component)]
+pub(crate) struct JumpForgiveness {
+    pub(crate) air_time: f32,
+    pub(crate) since_press: f32,
+}
+
+impl Default for JumpForgiveness {
+    fn default() -> Self {
+        Self {
+            air_time: f32::INFINITY,
+            since_press: f32::INFINITY,
+        }
+    }
+}
+
+/// Which keys and gamepads drive a player.
+#[derive(Component, Clone, Copy, PartialEq)]
+enum InputSource {
+    /// Every binding and every gamepad; used when playing alone.
+    Shared,
+    /// A/D to move, W or Space to jump, left Shift to dash.
+    KeyboardA,
+    /// Arrow keys to move, up arrow to jump, right Shift to dash.
+    KeyboardB,
+    Gamepad(Gamepad),
+}
+
+impl InputSource {
+    fn keys(self) -> &'static KeyBindings {
+        match self {
+            InputSource::Shared => &SHARED_KEYS,
+            InputSource::KeyboardA => &KEYBOARD_A_KEYS,
+            InputSource::KeyboardB => &KEYBOARD_B_KEYS,
+            InputSource::Gamepad(_) => &NO_KEYS,
+        }
+    }
+
+    fn uses_gamepad(self, gamepad: Gamepad) -> bool {
+        match self {
+            InputSource::Shared => true,
+            InputSource::Gamepad(own) => own == gamepad,
+            InputSource::KeyboardA | InputSource::KeyboardB => false,
+        }
+    }
+}
+
+struct KeyBindings {
+    left: &'static [KeyCode],
+    right: &'static [KeyCode],
+    up: &'static [KeyCode],
+    down: &'static [KeyCode],
+    jump: &'static [KeyCode],
+    dash: &'static [KeyCode],
+    grab: &'static [KeyCode],
+}
+
+// W is bound to jump, so only the arrow key counts as "up" here; holding jump
+// while running must not shorten the horizontal component.
+const SHARED_KEYS: KeyBindings = KeyBindings {
+    left: &[KeyCode::ArrowLeft, KeyCode::KeyA],
+    right: &[KeyCode::ArrowRight, KeyCode::KeyD],
+    up: &[KeyCode::ArrowUp],
+    down: &[KeyCode::ArrowDown, KeyCode::KeyS],
+    jump: &[KeyCode::Space, KeyCode::KeyW],
+    dash: &[KeyCode::ShiftLeft, KeyCode::ShiftRight],
+    grab: &[KeyCode::KeyE, KeyCode::ControlRight],
+};
+
+const KEYBOARD_A_KEYS: KeyBindings = KeyBindings {
+    left: &[KeyCode::KeyA],
+    right: &[KeyCode::KeyD],
+    up: &[],
+    down: &[KeyCode::KeyS],
+    jump: &[KeyCode::Space, KeyCode::KeyW],
+    dash: &[KeyCode::ShiftLeft],
+    grab: &[KeyCode::KeyE],
+};
+
+const KEYBOARD_B_KEYS: KeyBindings = KeyBindings {
+    left: &[KeyCode::ArrowLeft],
+    right: &[KeyCode::ArrowRight],
+    up: &[],
+    down: &[KeyCode::ArrowDown],
+    jump: &[KeyCode::ArrowUp],
+    dash: &[KeyCode::ShiftRight],
+    grab: &[KeyCode::ControlRight],
+};
+
+const NO_KEYS: KeyBindings = KeyBindings {
+    left: &[],
+    right: &[],
+    up: &[],
+    down: &[],
+    jump: &[],
+    dash: &[],
+    grab: &[],
+};
+
+/// One frame of input for a single player, read from their [`InputSource`].
+struct PlayerInput {
+    direction: Vec2,
+    jump_pressed: bool,
+    jump_held: bool,
+    dash_pressed: bool,
+    dash_held: bool,
+    grab_held: bool,
+}
+
+/// Where a player appears relative to the [`SpawnPoint`], so co-op players don't
+/// start inside each other.
+#[derive(Component)]
+pub(crate) struct SpawnOffset(pub(crate) Vec2);
+
+/// Where the player is drawn. Physics steps at a fixed 60 Hz that rarely
+/// matches the display, so a frame may see zero or two steps; drawing the
+/// player between the last two steps instead hides that stutter. The camera
+/// and the sprite follow `rendered`, physics keeps using the real position.
+#[derive(Component)]
+pub(crate) struct InterpolatedPosition {
+    previous: Vec2,
+    pub(crate) rendered: Vec2,
+}
+
+#[derive(Resource, Clone)]
+pub(crate) struct PlayerAssets {
+    pub(crate) texture: Handle<Image>,
+    pub(crate) layout: Handle<TextureAtlasLayout>,
+}
+
+fn setup_player_assets(
+    mut commands: Commands,
+    mut images: ResMut<Assets<Image>>,
+    mut atlases: ResMut<Assets<TextureAtlasLayout>>,
+) {
+    let pixels: Vec<[u8; 4]> = vec![
+        [255, 255, 255, 255], // idle
+        [120, 180, 255, 255], // jump
+        [255, 200, 120, 255], // fall
+        [255, 120, 160, 255], // dash
+        [235, 235, 235, 255], // run, first step
+        [205, 215, 225, 255], // run, second step
+    ];
+
+    let mut data = Vec::new();
+    for rgba in &pixels {
+        data.extend_from_slice(rgba);
+    }
+
+    let image = Image::new_fill(
+        Extent3d {
+            width: pixels.len() as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &data,
+        TextureFormat::Rgba8UnormSrgb,
+        RenderAssetUsages::RENDER_WORLD,
+    );
+
+    let texture = images.add(image);
+    let layout = atlases.add(TextureAtlasLayout::from_grid(
+        UVec2::ONE,
+        pixels.len() as u32,
+        1,
+        None,
+        None,
+    ));
+
+    commands.insert_resource(PlayerAssets { texture, layout });
+}
+
+fn spawn_player(
+    mut commands: Commands,
+    config: Res<PlayerConfig>,
+    timing: Res<TimingConfig>,
+    assets: Res<PlayerAssets>,
+    spawn: Res<SpawnPoint>,
+    level: Res<LevelData>,
+    level_config: Res<LevelConfig>,
+) {
+    if !config.coop {
+        spawn_player_entity(
+            &mut commands,
+            &config,
+            &timing,
+            &assets,
+            spawn.0,
+            Vec2::ZERO,
+            InputSource::Shared,
+            Color::WHITE,
+        );
+        return;
+    }
+
+    spawn_player_entity(
+        &mut commands,
+        &config,
+        &timing,
+        &assets,
+        spawn.0,
+        Vec2::ZERO,
+        InputSource::KeyboardA,
+        Color::WHITE,
+    );
+    spawn_player_entity(
+        &mut commands,
+        &config,
+        &timing,
+        &assets,
+        spawn.0,
+        second_player_offset(&level, &level_config, spawn.0),
+        InputSource::KeyboardB,
+        SECOND_PLAYER_TINT,
+    );
+}
+
+/// Where the second co-op player starts relative to the first: the cell to the
+/// right of the spawn, or to the left if that one is solid, or the spawn itself
+/// when both are.
+fn second_player_offset(level: &LevelData, config: &LevelConfig, spawn: Vec2) -> Vec2 {
+    let cell = level.world_to_grid(spawn, config);
+    [1, -1]
+        .into_iter()
+        .find(|&side: &isize| {
+            (cell.x as usize)
+                .checked_add_signed(side)
+                .is_some_and(|col| !level.is_solid(col, cell.y as usize))
+        })
+        .map_or(Vec2::ZERO, |side| {
+            Vec2::new(side as f32 * config.tile_size, 0.0)
+        })
+}
+
+fn spawn_player_entity(
+    commands: &mut Commands,
+    config: &PlayerConfig,
+    timing: &TimingConfig,
+    assets: &PlayerAssets,
+    spawn: Vec2,
+    offset: Vec2,
+    source: InputSource,
+    tint: Color,
+) {
+    let position = spawn + offset;
+    // The dash starts available, and without a pending ready flash.
+    let mut finished_cooldown = Timer::from_seconds(timing.dash_cooldown, TimerMode::Once);
+    finish_timer(&mut finished_cooldown);
+    let mut ready_flash = Timer::from_seconds(DASH_READY_FLASH_TIME, TimerMode::Once);
+    finish_timer(&mut ready_flash);
+    commands
+        .spawn((
+            SpriteBundle {
+                texture: assets.texture.clone(),
+                sprite: Sprite {
+                    color: tint,
+                    custom_size: Some(PLAYER_SIZE),
+                    anchor: Anchor::Custom(-config.sprite_offset / PLAYER_SIZE),
+                    ..default()
+                },
+                transform: Transform::from_xyz(position.x, position.y, z_layers::PLAYER),
+                ..default()
+            },
+            TextureAtlas {
+                layout: assets.layout.clone(),
+                index: 0,
+            },
+            (
+                Player,
+                source,
+                SpawnOffset(offset),
+                PlayerTint(tint),
+                InterpolatedPosition {
+                    previous: position,
+                    rendered: position,
+                },
+            ),
+            PlayerState::Standing,
+            Facing(1.0),
+            PlayerAnimation,
+            (
+                Grounded(false),
+                GroundingCache::default(),
+                ContactSides::default(),
+            ),
+            JumpForgiveness::default(),
+            DashMomentum::default(),
+            AnimationPlayback::default(),
+            IdleTime::default(),
+            DashTimers {
+                duration: Timer::from_seconds(timing.dash_duration, TimerMode::Once),
+                cooldown: finished_cooldown,
+                tint: Color::WHITE,
+                direction: 1.0,
+            },
+            (
+                DashReadyFlash(ready_flash),
+                EnergyMeter(1.0),
+                Stamina(1.0),
+                FallSpeed::default(),
+                SmoothedKeys::default(),
+            ),
+            (
+                RigidBody::Dynamic,
+                Collider::rectangle(PLAYER_COLLIDER_SIZE.x, PLAYER_COLLIDER_SIZE.y),
+                PLAYER_LAYERS,
+                LockedAxes::ROTATION_LOCKED,
+                LinearVelocity(Vec2::ZERO),
+                GravityScale(1.0),
+                Friction::new(1.0),
+                Restitution::new(0.0),
+            ),
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                EnergyBar,
+                SpriteBundle {
+                    sprite: Sprite {
+                        color: ENERGY_BAR_COLOR,
+                        custom_size: Some(ENERGY_BAR_SIZE),
+                        anchor: Anchor::CenterLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_translation(ENERGY_BAR_OFFSET.extend(0.1)),
+                    visibility: if config.dash_mode == DashMode::Sustained {
+                        Visibility::Inherited
+                    } else {
+                        Visibility::Hidden
+                    },
+                    ..default()
+                },
+            ));
+        });
+}
+
+/// Sizes each [`EnergyBar`] to its player's stamina or energy, and follows the
+/// interpolated sprite rather than the physics position so the two don't
+/// drift apart between steps.
+fn update_energy_bars(
+    config: Res<PlayerConfig>,
+    players: Query<
+        (
+            &Transform,
+            &InterpolatedPosition,
+            &PlayerState,
+            &EnergyMeter,
+            &Stamina,
+        ),
+        With<Player>,
+    >,
+    mut bars: Query<
+        (&Parent, &mut Transform, &mut Sprite, &mut Visibility),
+        (With<EnergyBar>, Without<Player>),
+    >,
+) {
+    for (parent, mut transform, mut sprite, mut visibility) in &mut bars {
+        let Ok((player, interpolated, state, energy, stamina)) = players.get(parent.get()) else {
+            continue;
+        };
+        let lag = interpolated.rendered - player.translation.truncate();
+        transform.translation = (ENERGY_BAR_OFFSET + lag).extend(0.1);
+
+        let (fill, color) = if *state == PlayerState::Grabbing {
+            (stamina.0, STAMINA_BAR_COLOR)
+        } else {
+            (energy.0, ENERGY_BAR_COLOR)
+        };
+        sprite.custom_size = Some(ENERGY_BAR_SIZE * Vec2::new(fill, 1.0));
+        sprite.color = color;
+        *visibility = if *state == PlayerState::Grabbing || config.dash_mode == DashMode::Sustained
+        {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+    }
+}
+
+/// Moves every player to a point, each keeping its [`SpawnOffset`], and resets
+/// their motion and dash state without touching the level.
+#[derive(Event)]
+pub(crate) struct WarpPlayers(pub(crate) Vec2);
+
+/// Puts the players back at the spawn when the level restarts, or wherever a
+/// [`WarpPlayers`] sends them. A restart wins if both arrive in one frame.
+fn reset_players(
+    time: Res<Time>,
+    mut restarted: EventReader<LevelRestarted>,
+    mut warps: EventReader<WarpPlayers>,
+    spawn: Res<SpawnPoint>,
+    mut query: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut LinearVelocity,
+            &mut PlayerState,
+            &mut Grounded,
+            &mut DashTimers,
+            &mut JumpForgiveness,
+            &mut DashMomentum,
+            &mut EnergyMeter,
+            &mut Stamina,
+            &SpawnOffset,
+        ),
+        With<Player>,
+    >,
+) {
+    let restart = restarted.read().last().map(|_| spawn.0);
+    let warp = warps.read().last().map(|warp| warp.0);
+    let Some(target) = restart.or(warp) else {
+        return;
+    };
+
+    for (
+        entity,
+        mut transform,
+        mut velocity,
+        mut state,
+        mut grounded,
+        mut dash_timers,
+        mut forgiveness,
+        mut momentum,
+        mut energy,
+        mut stamina,
+        offset,
+    ) in &mut query
+    {
+        let position = target + offset.0;
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        set_state(
+            &mut state,
+            PlayerState::Standing,
+            StateCause::Reset,
+            entity,
+            &time,
+            velocity.0,
+        );
+        velocity.0 = Vec2::ZERO;
+        grounded.0 = false;
+        dash_timers.duration.reset();
+        finish_timer(&mut dash_timers.cooldown);
+        *forgiveness = JumpForgiveness::default();
+        momentum.0 = 0.0;
+        energy.0 = 1.0;
+        stamina.0 = 1.0;
+    }
+}
+
+/// Keyboard direction as eased by `smooth_keyboard`; sticks are analog already.
+#[derive(Component, Default)]
+struct SmoothedKeys(Vec2);
+
+impl SmoothedKeys {
+    /// Moves each axis towards the held keys' direction by at most `step`.
+    fn ramp(&mut self, target: Vec2, step: f32) -> Vec2 {
+        let change = (target - self.0).clamp(Vec2::splat(-step), Vec2::splat(step));
+        self.0 += change;
+        self.0
+    }
+}
+
+/// Desired movement direction from a player's keys and gamepads' left sticks, with
+/// its length clamped to 1 so combined or diagonal input isn't faster.
+///
+/// `smoothing`, when given, eases the keyboard part by at most its step per
+/// axis before the sticks are added.
+fn read_move_input(
+    source: InputSource,
+    keyboard: &ButtonInput<KeyCode>,
+    gamepads: &Gamepads,
+    axes: &Axis<GamepadAxis>,
+    smoothing: Option<(&mut SmoothedKeys, f32)>,
+) -> Vec2 {
+    let keys = source.keys();
+    let mut direction = Vec2::ZERO;
+    if keyboard.any_pressed(keys.left.iter().copied()) {
+        direction.x -= 1.0;
+    }
+    if keyboard.any_pressed(keys.right.iter().copied()) {
+        direction.x += 1.0;
+    }
+    if keyboard.any_pressed(keys.up.iter().copied()) {
+        direction.y += 1.0;
+    }
+    if keyboard.any_pressed(keys.down.iter().copied()) {
+        direction.y -= 1.0;
+    }
+    if let Some((smoothed, step)) = smoothing {
+        direction = smoothed.ramp(direction, step);
+    }
+
+    for gamepad in gamepads
+        .iter()
+        .filter(|&gamepad| source.uses_gamepad(gamepad))
+    {
+        direction.x += axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or(0.0);
+        direction.y += axes
+            .get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or(0.0);
+    }
+
+    direction.clamp_length_max(1.0)
+}
+
+fn read_player_input(
+    source: InputSource,
+    keyboard: &ButtonInput<KeyCode>,
+    gamepads: &Gamepads,
+    buttons: &ButtonInput<GamepadButton>,
+    axes: &Axis<GamepadAxis>,
+    smoothing: Option<(&mut SmoothedKeys, f32)>,
+) -> PlayerInput {
+    let keys = source.keys();
+    let pad_pressed = |button: GamepadButtonType| {
+        gamepads
+            .iter()
+            .filter(|&gamepad| source.uses_gamepad(gamepad))
+            .any(|gamepad| buttons.pressed(GamepadButton::new(gamepad, button)))
+    };
+    let pad_just_pressed = |button: GamepadButtonType| {
+        gamepads
+            .iter()
+            .filter(|&gamepad| source.uses_gamepad(gamepad))
+            .any(|gamepad| buttons.just_pressed(GamepadButton::new(gamepad, button)))
+    };
+
+    PlayerInput {
+        direction: read_move_input(source, keyboard, gamepads, axes, smoothing),
+        jump_pressed: keyboard.any_just_pressed(keys.jump.iter().copied())
+            || pad_just_pressed(GamepadButtonType::South),
+        jump_held: keyboard.any_pressed(keys.jump.iter().copied())
+            || pad_pressed(GamepadButtonType::South),
+        dash_pressed: keyboard.any_just_pressed(keys.dash.iter().copied())
+            || pad_just_pressed(GamepadButtonType::East),
+        dash_held: keyboard.any_pressed(keys.dash.iter().copied())
+            || pad_pressed(GamepadButtonType::East),
+        grab_held: keyboard.any_pressed(keys.grab.iter().copied())
+            || pad_pressed(GamepadButtonType::RightTrigger),
+    }
+}
+
+/// Hands the second co-op player over to the first gamepad that connects while
+/// they are still on the keyboard, and back to the keyboard when their gamepad
+/// is unplugged. Bevy drops a disconnected gamepad's axes, so no stick value is
+/// left stuck for the shared solo player either.
+fn handle_gamepad_connections(
+    mut connections: EventReader<GamepadConnectionEvent>,
+    mut query: Query<&mut InputSource, With<Player>>,
+) {
+    for event in connections.read() {
+        if event.disconnected() {
+            info!("gamepad {} disconnected", event.gamepad.id);
+            for mut source in &mut query {
+                if *source == InputSource::Gamepad(event.gamepad) {
+                    info!("player 2 falls back to the keyboard");
+                    *source = InputSource::KeyboardB;
+                }
+            }
+            continue;
+        }
+        info!("gamepad {} connected", event.gamepad.id);
+        let taken = query
+            .iter()
+            .any(|source| *source == InputSource::Gamepad(event.gamepad));
+        if taken {
+            continue;
+        }
+        if let Some(mut source) = query
+            .iter_mut()
+            .find(|source| **source == InputSource::KeyboardB)
+        {
+            info!("gamepad {} now controls player 2", event.gamepad.id);
+            *source = InputSource::Gamepad(event.gamepad);
+        }
+    }
+}
+
+fn toggle_rubber_mode(keyboard: Res<ButtonInput<KeyCode>>, mut rubber: ResMut<RubberMode>) {
+    if keyboard.just_pressed(KeyCode::KeyB) {
+        rubber.0 = !rubber.0;
+        info!("rubber mode {}", if rubber.0 { "on" } else { "off" });
+    }
+}
+
+fn toggle_facing_lock(keyboard: Res<ButtonInput<KeyCode>>, mut lock: ResMut<FacingLock>) {
+    if keyboard.just_pressed(KeyCode::KeyL) {
+        lock.0 = !lock.0;
+        info!("facing lock {}", if lock.0 { "on" } else { "off" });
+    }
+}
+
+fn toggle_gravity_flip(keyboard: Res<ButtonInput<KeyCode>>, mut flip: ResMut<GravityFlip>) {
+    if keyboard.just_pressed(KeyCode::KeyG) {
+        flip.0 = !flip.0;
+        info!("gravity {}", if flip.0 { "flipped" } else { "normal" });
+    }
+}
+
+fn apply_gravity_flip(flip: Res<GravityFlip>, mut gravity: ResMut<Gravity>) {
+    if flip.is_changed() {
+        gravity.0 = Vec2::NEG_Y * GRAVITY * flip.up();
+    }
+}
+
+fn apply_rubber_mode(
+    config: Res<PlayerConfig>,
+    rubber: Res<RubberMode>,
+    mut query: Query<&mut Restitution, With<Player>>,
+) {
+    if !rubber.is_changed() {
+        return;
+    }
+
+    let coefficient = if rubber.0 {
+        config.rubber_restitution
+    } else {
+        0.0
+    };
+    for mut restitution in &mut query {
+        // Max so the bounce isn't averaged away against the tiles' zero restitution.
+        *restitution = Restitution::new(coefficient).with_combine_rule(CoefficientCombine::Max);
+    }
+}
+
+fn player_input(
+    time: Res<Time>,
+    config: Res<PlayerConfig>,
+    timing: Res<TimingConfig>,
+    accessibility: Res<AccessibilityConfig>,
+    assist: Res<AssistMode>,
+    dash_tints: Res<DashTints>,
+    facing_lock: Res<FacingLock>,
+    gravity_flip: Res<GravityFlip>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_input: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut query: Query<
+        (
+            Entity,
+            &mut LinearVelocity,
+            &mut PlayerState,
+            &mut Facing,
+            &mut DashTimers,
+            &mut JumpForgiveness,
+            &mut DashMomentum,
+            &mut EnergyMeter,
+            &mut Stamina,
+            &Grounded,
+            &ContactSides,
+            &mut SmoothedKeys,
+            &InputSource,
+        ),
+        With<Player>,
+    >,
+) {
+    for (
+        entity,
+        mut velocity,
+        mut state,
+        mut facing,
+        mut dash_timers,
+        mut forgiveness,
+        mut momentum,
+        mut energy,
+        mut stamina,
+        grounded,
+        sides,
+        mut smoothed_keys,
+        source,
+    ) in &mut query
+    {
+        let smoothing = accessibility.smooth_keyboard.then(|| {
+            let step = if timing.input_ramp > 0.0 {
+                time.delta_seconds() / timing.input_ramp
+            } else {
+                f32::INFINITY
+            };
+            (&mut *smoothed_keys, step)
+        });
+        let input = read_player_input(
+            *source,
+            &keyboard,
+            &gamepads,
+            &button_input,
+            &axes,
+            smoothing,
+        );
+        let axis = input.direction.x;
+
+        let on_ground = grounded.0;
+        let steered = axis * config.move_speed;
+        velocity.x = if on_ground {
+            steered
+        } else {
+            // The share of the gap closed per 1/60 s, so the feel doesn't depend
+            // on the frame rate.
+            let retained = 1.0 - config.air_control.clamp(0.0, 1.0);
+            let blend = 1.0 - retained.powf(time.delta_seconds() * 60.0);
+            velocity.x + (steered - velocity.x) * blend
+        };
+
+        if momentum.0 != 0.0 {
+            if *state == PlayerState::Standing || axis * momentum.0 < 0.0 {
+                momentum.0 = 0.0;
+            } else {
+                velocity.x = momentum.0;
+            }
+        }
+
+        if axis.abs() > 0.1 && !facing_lock.0 {
+            facing.0 = axis.signum();
+        }
+
+        dash_timers.cooldown.tick(time.delta());
+
+        let delta = time.delta_seconds();
+        forgiveness.air_time = if on_ground {
+            0.0
+        } else {
+            forgiveness.air_time + delta
+        };
+        forgiveness.since_press = if input.jump_pressed {
+            0.0
+        } else {
+            forgiveness.since_press + delta
+        };
+
+        let mut wants_jump = input.jump_pressed;
+        let mut can_jump = on_ground || assist.jumps();
+        if accessibility.generous_jump {
+            wants_jump |= forgiveness.since_press <= timing.jump_buffer;
+            can_jump |= forgiveness.air_time <= timing.coyote_time;
+        }
+        if accessibility.auto_hop && on_ground {
+            wants_jump |= input.jump_held;
+        }
+
+        if can_jump && wants_jump {
+            if *state == PlayerState::Dashing {
+                momentum.0 = dash_timers.direction * config.dash_speed * config.dash_jump_retain;
+                velocity.x = momentum.0;
+            }
+            velocity.y = config.jump_speed * gravity_flip.up();
+            set_state(
+                &mut state,
+                PlayerState::Jumping,
+                StateCause::Input,
+                entity,
+                &time,
+                velocity.0,
+            );
+            forgiveness.air_time = f32::INFINITY;
+            forgiveness.since_press = f32::INFINITY;
+        }
+
+        let sustained = config.dash_mode == DashMode::Sustained;
+        let dash_ready =
+            assist.dashes() || (dash_timers.cooldown.finished() && (!sustained || energy.0 > 0.0));
+        if input.dash_pressed && dash_ready {
+            dash_timers.direction = if axis.abs() > 0.1 {
+                axis.signum()
+            } else {
+                facing.0
+            };
+            let direction = Vec2::new(dash_timers.direction, 0.0);
+            dash_timers.duration.reset();
+            dash_timers.cooldown.reset();
+            dash_timers.tint = dash_tints.horizontal;
+            velocity.0 = direction * config.dash_speed;
+            set_state(
+                &mut state,
+                PlayerState::Dashing,
+                StateCause::Input,
+                entity,
+                &time,
+                velocity.0,
+            );
+        }
+
+        if matches!(*state, PlayerState::Dashing) {
+            let ended = if sustained {
+                energy.0 = if assist.dashes() {
+                    1.0
+                } else {
+                    (energy.0 - config.dash_energy_drain * delta).max(0.0)
+                };
+                if !input.dash_held {
+                    Some(StateCause::Input)
+                } else if energy.0 <= 0.0 {
+                    Some(StateCause::Energy)
+                } else {
+                    None
+                }
+            } else if dash_timers.duration.tick(time.delta()).finished() {
+                Some(StateCause::Timer)
+            } else {
+                None
+            };
+            if let Some(cause) = ended {
+                set_state(
+                    &mut state,
+                    PlayerState::Falling,
+                    cause,
+                    entity,
+                    &time,
+                    velocity.0,
+                );
+            } else {
+                velocity.y = 0.0;
+                velocity.x = dash_timers.direction * config.dash_speed;
+            }
+        } else if sustained {
+            energy.0 = (energy.0 + config.dash_energy_regen * delta).min(1.0);
+        }
+
+        let wall = if sides.left {
+            -1.0
+        } else if sides.right {
+            1.0
+        } else {
+            0.0
+        };
+        if *state == PlayerState::Grabbing {
+            let release = if on_ground {
+                Some((PlayerState::Standing, StateCause::Grounding))
+            } else if !input.grab_held {
+                Some((PlayerState::Falling, StateCause::Input))
+            } else if wall == 0.0 {
+                Some((PlayerState::Falling, StateCause::Wall))
+            } else if stamina.0 <= 0.0 {
+                Some((PlayerState::Falling, StateCause::Stamina))
+            } else {
+                None
+            };
+            if let Some((next, cause)) = release {
+                set_state(&mut state, next, cause, entity, &time, velocity.0);
+            }
+        } else if config.wall_grab
+            && input.grab_held
+            && wall != 0.0
+            && !on_ground
+            && stamina.0 > 0.0
+            && matches!(*state, PlayerState::Jumping | PlayerState::Falling)
+        {
+            set_state(
+                &mut state,
+                PlayerState::Grabbing,
+                StateCause::Input,
+                entity,
+                &time,
+                velocity.0,
+            );
+        }
+
+        if *state == PlayerState::Grabbing {
+            // Jump doubles as up, since the left half of the keyboard has no
+            // other up key.
+            let climb = if input.jump_held {
+                1.0
+            } else {
+                input.direction.y.clamp(-1.0, 1.0)
+            };
+            velocity.x = wall * GRAB_WALL_PUSH;
+            velocity.y = climb * config.grab_climb_speed * gravity_flip.up();
+            momentum.0 = 0.0;
+            let drain = config.grab_stamina_drain + climb.abs() * config.grab_climb_drain;
+            stamina.0 = (stamina.0 - drain * delta).max(0.0);
+        } else if on_ground {
+            stamina.0 = 1.0;
+        }
+    }
+}
+
+/// Starts a [`DashReadyFlash`] on the frame a dash cooldown runs out.
+fn flash_dash_ready(mut query: Query<(&DashTimers, &mut DashReadyFlash), With<Player>>) {
+    for (dash_timers, mut flash) in &mut query {
+        if dash_timers.cooldown.just_finished() {
+            flash.0.reset();
+        }
+    }
+}
+
+/// Nudges a rising player sideways when only one edge of their head is about to
+/// hit a ceiling, so clipping a corner doesn't kill the jump.
+fn correct_ceiling_corners(
+    time: Res<Time>,
+    config: Res<PlayerConfig>,
+    gravity_flip: Res<GravityFlip>,
+    spatial_query: SpatialQuery,
+    mut query: Query<(&mut Transform, &LinearVelocity, &PlayerState), With<Player>>,
+    solids: Query<&ColliderAabb, Without<Sensor>>,
+) {
+    let up = gravity_flip.up();
+    for (mut transform, velocity, state) in &mut query {
+        let rise = velocity.y * up;
+        if rise <= 0.0 || *state == PlayerState::Dashing {
+            continue;
+        }
+
+        // Rays start a hair inside the collider edges so they don't graze walls we're
+        // sliding along.
+        let half = PLAYER_COLLIDER_SIZE * 0.5 - Vec2::splat(0.5);
+        let head = transform.translation.truncate() + Vec2::new(0.0, half.y * up);
+        let reach = rise * time.delta_seconds() + 1.0;
+        // The player isn't on the solid layer, so the mask also skips their own collider.
+        let filter = SpatialQueryFilter::from_mask(GameLayer::Solid.bit());
+        let ceiling_above = |x: f32| {
+            spatial_query.cast_ray_predicate(
+                Vec2::new(x, head.y),
+                gravity_flip.up_dir(),
+                reach,
+                true,
+                filter.clone(),
+                &|hit| solids.contains(hit),
+            )
+        };
+
+        let left = ceiling_above(head.x - half.x);
+        let right = ceiling_above(head.x + half.x);
+        let nudge = match (left, right) {
+            (Some(hit), None) => solids
+                .get(hit.entity)
+                .ok()
+                .map(|aabb| aabb.max.x - (head.x - half.x) + 0.5),
+            (None, Some(hit)) => solids
+                .get(hit.entity)
+                .ok()
+                .map(|aabb| aabb.min.x - (head.x + half.x) - 0.5),
+            _ => None,
+        };
+
+        let Some(nudge) = nudge.filter(|nudge| nudge.abs() <= config.corner_correction) else {
+            continue;
+        };
+
+        // Only commit if the leading edge is clear at the nudged position.
+        let leading_edge = head.x + nudge + half.x * nudge.signum();
+        if ceiling_above(leading_edge).is_none() {
+            transform.translation.x += nudge;
+        }
+    }
+}
+
+/// Lifts a grounded, walking player onto a lip no taller than
+/// [`PlayerConfig::step_height`], so low ledges don't stop them like a wall.
+fn step_up_ledges(
+    time: Res<Time>,
+    config: Res<PlayerConfig>,
+    gravity_flip: Res<GravityFlip>,
+    spatial_query: SpatialQuery,
+    mut query: Query<(&mut Transform, &LinearVelocity, &Grounded, &PlayerState), With<Player>>,
+) {
+    let filter = SpatialQueryFilter::from_mask(GameLayer::Solid.bit());
+    let up = gravity_flip.up();
+    for (mut transform, velocity, grounded, state) in &mut query {
+        if !grounded.0 || *state != PlayerState::Standing || velocity.x.abs() < 1.0 {
+            continue;
+        }
+
+        let direction = velocity.x.signum();
+        let center = transform.translation.truncate();
+        let feet = center.y - PLAYER_HALF_HEIGHT * up;
+        let half_width = PLAYER_COLLIDER_SIZE.x * 0.5;
+        let reach = half_width + velocity.x.abs() * time.delta_seconds() + 1.0;
+
+        // Something right in front of the feet...
+        let Some(wall) = spatial_query.cast_ray(
+            Vec2::new(center.x, feet + up),
+            if direction > 0.0 {
+                Dir2::X
+            } else {
+                Dir2::NEG_X
+            },
+            reach,
+            true,
+            filter.clone(),
+        ) else {
+            continue;
+        };
+
+        // ...whose top, found by probing down from just above the step height, is
+        // low enough. A taller obstacle puts the probe's origin inside it, which
+        // reports the full height and is rejected.
+        let probe_x = center.x + direction * (wall.time_of_impact + 0.5);
+        let probe_top = feet + (config.step_height + 1.0) * up;
+        let Some(top) = spatial_query.cast_ray(
+            Vec2::new(probe_x, probe_top),
+            -gravity_flip.up_dir(),
+            config.step_height + 1.0,
+            true,
+            filter.clone(),
+        ) else {
+            continue;
+        };
+
+        let rise = config.step_height + 1.0 - top.time_of_impact;
+        if rise > 0.0 && rise <= config.step_height {
+            transform.translation.y += (rise + 0.5) * up;
+        }
+    }
+}
+
+pub(crate) fn update_player_state(
+    time: Res<Time>,
+    config: Res<PlayerConfig>,
+    rubber: Res<RubberMode>,
+    gravity_flip: Res<GravityFlip>,
+    mut query: Query<
+        (
+            Entity,
+            &LinearVelocity,
+            &mut PlayerState,
+            &mut Grounded,
+            &mut GravityScale,
+            &mut GroundingCache,
+            &mut ContactSides,
+            &mut FallSpeed,
+            Ref<CollidingEntities>,
+            &GlobalTransform,
+        ),
+        With<Player>,
+    >,
+    solids: SolidContacts,
+    mut grounded_changed: EventWriter<GroundedChanged>,
+    mut landed: EventWriter<Landed>,
+) {
+    for (
+        entity,
+        velocity,
+        mut state,
+        mut grounded,
+        mut gravity_scale,
+        mut cache,
+        mut sides,
+        mut fall,
+        collisions,
+        transform,
+    ) in &mut query
+    {
+        let position = transform.translation().truncate();
+        let up = gravity_flip.up();
+        let rise = velocity.y * up;
+
+        // Walking off a ledge ends the floor contact, which marks `CollidingEntities`
+        // changed, so the recompute still happens on the frame the player leaves it.
+        // A gravity flip turns the floor into the ceiling without moving anything.
+        let stale = !grounded.0
+            || collisions.is_changed()
+            || gravity_flip.is_changed()
+            || cache.is_dirty(position.y, velocity.y);
+        if stale {
+            *sides = contact_sides(position, up, &collisions, &solids);
+            let now_grounded = sides.floor;
+            if now_grounded != grounded.0 {
+                grounded_changed.send(GroundedChanged {
+                    entity,
+                    grounded: now_grounded,
+                });
+                if now_grounded {
+                    landed.send(Landed {
+                        entity,
+                        speed: fall.0,
+                    });
+                }
+            }
+            grounded.0 = now_grounded;
+            cache.y = position.y;
+            cache.velocity_y = velocity.y;
+            cache.recomputes += 1;
+        }
+
+        fall.0 = if grounded.0 { 0.0 } else { (-rise).max(0.0) };
+
+        // A bouncing player leaves the ground upwards on their own; don't treat the
+        // brief floor contact mid-bounce as landing.
+        let bouncing = rubber.0 && velocity.y.abs() > BOUNCE_REST_SPEED;
+
+        let transition = match *state {
+            PlayerState::Standing => {
+                if bouncing && rise > 0.0 {
+                    Some((PlayerState::Jumping, StateCause::Bounce))
+                } else if !grounded.0 {
+                    Some((PlayerState::Falling, StateCause::Grounding))
+                } else {
+                    None
+                }
+            }
+            PlayerState::Jumping => {
+                (rise <= 0.0).then_some((PlayerState::Falling, StateCause::Apex))
+            }
+            PlayerState::Falling => {
+                if bouncing && rise > 0.0 {
+                    Some((PlayerState::Jumping, StateCause::Bounce))
+                } else if grounded.0 && !bouncing {
+                    Some((PlayerState::Standing, StateCause::Grounding))
+                } else {
+                    None
+                }
+            }
+            // handled in input system
+            PlayerState::Dashing | PlayerState::Grabbing => None,
+        };
+        if let Some((next, cause)) = transition {
+            set_state(&mut state, next, cause, entity, &time, velocity.0);
+        }
+
+        gravity_scale.0 = if matches!(*state, PlayerState::Dashing | PlayerState::Grabbing) {
+            0.0
+        } else if rise > 0.0 {
+            config.rise_gravity_scale
+        } else {
+            config.fall_gravity_scale
+        };
+    }
+}
+
+/// Colliders that can hold the player up or block them: anything on the solid
+/// layer, whether a merged tile run or a closed door, but no sensors.
+type SolidContacts<'w, 's> =
+    Query<'w, 's, (&'static ColliderAabb, &'static CollisionLayers), Without<Sensor>>;
+
+/// Classifies each touching solid by where it sits relative to the player's box:
+/// below the feet is floor, above the head is ceiling, and beside it is a wall
+/// on the side it is on. A tile that only meets the box at a corner, like the
+/// wall cell above the one being pushed, counts as neither. `up` is
+/// [`GravityFlip::up`]; with gravity flipped the floor is the tile above.
+fn contact_sides(
+    player_pos: Vec2,
+    up: f32,
+    collisions: &CollidingEntities,
+    solids: &SolidContacts,
+) -> ContactSides {
+    let half = PLAYER_COLLIDER_SIZE * 0.5;
+    let (feet, head) = (player_pos.y - half.y, player_pos.y + half.y);
+    let (left, right) = (player_pos.x - half.x, player_pos.x + half.x);
+    let mut sides = ContactSides::default();
+    for aabb in collisions
+        .iter()
+        .filter_map(|entity| solids.get(*entity).ok())
+        .filter(|(_, layers)| layers.memberships.has_all(GameLayer::Solid.bit()))
+        .map(|(aabb, _)| aabb)
+    {
+        let beside = aabb.min.y < head - GROUND_TOLERANCE && aabb.max.y > feet + GROUND_TOLERANCE;
+        let over = aabb.min.x < right - GROUND_TOLERANCE && aabb.max.x > left + GROUND_TOLERANCE;
+        if over && aabb.max.y <= feet + GROUND_TOLERANCE {
+            sides.floor = true;
+        } else if over && aabb.min.y >= head - GROUND_TOLERANCE {
+            sides.ceiling = true;
+        } else if beside && aabb.max.x <= player_pos.x {
+            sides.left = true;
+        } else if beside && aabb.min.x >= player_pos.x {
+            sides.right = true;
+        }
+    }
+    if up < 0.0 {
+        std::mem::swap(&mut sides.floor, &mut sides.ceiling);
+    }
+    sides
+}
+
+fn record_previous_positions(mut players: Query<(&Position, &mut InterpolatedPosition)>) {
+    for (position, mut interpolated) in &mut players {
+        interpolated.previous = position.0;
+    }
+}
+
+/// Blends from the previous physics step to the latest one by how far real
+/// time has run into the next step.
+pub(crate) fn interpolate_player_positions(
+    time: Res<Time<Physics>>,
+    mut players: Query<(&Position, &mut InterpolatedPosition)>,
+) {
+    let fraction = match time.timestep_mode() {
+        TimestepMode::Fixed {
+            delta, overstep, ..
+        } => (overstep.as_secs_f32() / delta.as_secs_f32()).min(1.0),
+        TimestepMode::FixedOnce { .. } | TimestepMode::Variable { .. } => 1.0,
+    };
+    for (position, mut interpolated) in &mut players {
+        interpolated.rendered = interpolated.previous.lerp(position.0, fraction);
+    }
+}
+
+/// Clamps sideways speed after everything in the chain that sets it, so stacked
+/// pushes such as dash-jump momentum and bounces can't add up past
+/// [`PlayerConfig::max_horizontal_speed`].
+fn cap_horizontal_speed(
+    config: Res<PlayerConfig>,
+    mut query: Query<(&mut LinearVelocity, &PlayerState), With<Player>>,
+) {
+    for (mut velocity, state) in &mut query {
+        let cap = if *state == PlayerState::Dashing {
+            config.max_dash_speed
+        } else {
+            config.max_horizontal_speed
+        };
+        velocity.x = velocity.x.clamp(-cap, cap);
+    }
+}
+
+fn apply_ground_snap(mut query: Query<(&mut Transform, &Grounded), With<Player>>) {
+    // Helps keep the player sitting on the floor instead of hovering because of numerical errors.
+    for (mut transform, grounded) in &mut query {
+        if grounded.0 {
+            transform.translation.y = transform.translation.y.round();
+        }
+    }
+}
+
+fn animate_player(
+    time: Res<Time>,
+    config: Res<AnimationConfig>,
+    player_config: Res<PlayerConfig>,
+    gravity_flip: Res<GravityFlip>,
+    mut query: Query<
+        (
+            &PlayerState,
+            &LinearVelocity,
+            &mut TextureAtlas,
+            &mut AnimationPlayback,
+            &mut IdleTime,
+            &mut Sprite,
+            &DashTimers,
+            &PlayerTint,
+            &mut DashReadyFlash,
+            &Facing,
+        ),
+        With<PlayerAnimation>,
+    >,
+) {
+    for (
+        state,
+        velocity,
+        mut atlas,
+        mut playback,
+        mut idle,
+        mut sprite,
+        dash,
+        tint,
+        mut flash,
+        facing,
+    ) in &mut query
+    {
+        let resting = *state == PlayerState::Standing && velocity.length() < 1.0;
+        idle.0 = if resting {
+            idle.0 + time.delta_seconds()
+        } else {
+            0.0
+        };
+
+        let idling = idle.0 >= config.idle_delay;
+        let running = *state == PlayerState::Standing && velocity.x.abs() >= 1.0;
+        let (clip, rate) = if idling {
+            (&config.idle, 1.0)
+        } else if running {
+            let rate = config.run_playback_rate(velocity.x, player_config.move_speed);
+            (&config.running, rate)
+        } else {
+            (config.clip(*state), 1.0)
+        };
+
+        // Breathe by resizing the sprite: the transform's scale would also
+        // scale the collider.
+        let breathing = if idling {
+            let breath = ((idle.0 - config.idle_delay) * config.breathing_rate * TAU).sin()
+                * config.breathing_amplitude;
+            Vec2::new(1.0 - breath * 0.5, 1.0 + breath)
+        } else {
+            Vec2::ONE
+        };
+        sprite.custom_size = Some(PLAYER_SIZE * breathing);
+
+        let state_tint = if *state == PlayerState::Dashing {
+            dash.tint
+        } else {
+            clip.tint
+        };
+        sprite.color = multiply_colors(tint.0, state_tint);
+        sprite.flip_x = facing.0 < 0.0;
+        sprite.flip_y = gravity_flip.0;
+        if !flash.0.tick(time.delta()).finished() {
+            sprite.color = multiply_colors(sprite.color, DASH_READY_FLASH_COLOR);
+        }
+
+        let frames = &clip.frames;
+        // Switching to a clip that doesn't show the current frame starts it over.
+        if frames.get(playback.frame).map(|&(index, _)| index) != Some(atlas.index) {
+            *playback = AnimationPlayback::default();
+        }
+
+        playback.elapsed += time.delta_seconds() * rate;
+        loop {
+            // Clamped so a zero duration can't spin forever.
+            let duration = frames[playback.frame].1.max(0.001);
+            let last = playback.frame + 1 == frames.len();
+            if playback.elapsed < duration || (last && !clip.looping) {
+                break;
+            }
+            playback.elapsed -= duration;
+            playback.frame = if last { 0 } else { playback.frame + 1 };
+        }
+        atlas.index = frames[playback.frame].0;
+    }
+}
+
+// --- No-clip (debug) ------------------------------------------------------
+
+#[cfg(feature = "debug")]
+const NO_CLIP_SPEED: f32 = 900.0;
+
+/// F4 lets the players fly through walls in any direction, for reaching a spot in
+/// a big level without playing up to it. Only built with the `debug` feature.
+#[cfg(feature = "debug")]
+struct NoClipPlugin;
+
+#[cfg(feature = "debug")]
+impl Plugin for NoClipPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            (toggle_no_clip, fly_no_clip)
+                .chain()
+                .after(apply_ground_snap)
+                .in_set(GameplaySet),
+        );
+    }
+}
+
+#[cfg(feature = "debug")]
+#[derive(Component)]
+struct NoClip;
+
+#[cfg(feature = "debug")]
+fn toggle_no_clip(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    spatial_query: SpatialQuery,
+    level_config: Res<LevelConfig>,
+    mut players: Query<
+        (
+            Entity,
+            &mut Transform,
+            &mut LinearVelocity,
+            &Collider,
+            Has<NoClip>,
+        ),
+        With<Player>,
+    >,
+) {
+    if !keyboard.just_pressed(KeyCode::F4) {
+        return;
+    }
+
+    for (entity, mut transform, mut velocity, collider, no_clip) in &mut players {
+        velocity.0 = Vec2::ZERO;
+        if !no_clip {
+            // Kinematic bodies ignore gravity and aren't pushed out of solids.
+            commands
+                .entity(entity)
+                .insert((NoClip, RigidBody::Kinematic));
+            info!("no-clip on");
+            continue;
+        }
+
+        let position = transform.translation.truncate();
+        let free = free_spot_near(&spatial_query, collider, position, level_config.tile_size);
+        transform.translation.x = free.x;
+        transform.translation.y = free.y;
+        commands
+            .entity(entity)
+            .remove::<NoClip>()
+            .insert(RigidBody::Dynamic);
+        info!("no-clip off");
+    }
+}
+
+/// Closest position to `position`, searching outwards in quarter tiles, where
+/// `collider` doesn't overlap anything solid.
+#[cfg(feature = "debug")]
+fn free_spot_near(
+    spatial_query: &SpatialQuery,
+    collider: &Collider,
+    position: Vec2,
+    tile_size: f32,
+) -> Vec2 {
+    let filter = SpatialQueryFilter::from_mask(GameLayer::Solid.bit());
+    let step = tile_size * 0.25;
+    let directions = [Vec2::Y, Vec2::NEG_X, Vec2::X, Vec2::NEG_Y];
+    let candidates = std::iter::once(position)
+        .chain((1..=16).flat_map(|ring| directions.map(|dir| position + dir * step * ring as f32)));
+    for candidate in candidates {
+        if spatial_query
+            .shape_intersections(collider, candidate, 0.0, filter.clone())
+            .is_empty()
+        {
+            return candidate;
+        }
+    }
+    position
+}
+
+/// Runs after the normal movement systems and overrides whatever velocity they
+/// set.
+#[cfg(feature = "debug")]
+fn fly_no_clip(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    gamepads: Res<Gamepads>,
+    button_input: Res<ButtonInput<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut players: Query<(&mut LinearVelocity, &InputSource), (With<Player>, With<NoClip>)>,
+) {
+    for (mut velocity, source) in &mut players {
+        let input = read_player_input(*source, &keyboard, &gamepads, &button_input, &axes, None);
+        let mut direction = input.direction;
+        if input.jump_held {
+            direction.y = 1.0;
+        }
+        velocity.0 = direction.normalize_or_zero() * NO_CLIP_SPEED;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::level::{LevelData, TileCollider};
+    use crate::testing::{
+        connect_gamepad, disconnect_gamepad, press, release, set_stick, single, step, test_app,
+        TEST_ROOM,
+    };
+    use crate::PHYSICS_HZ;
+
+    /// Presses and releases `key` within one frame.
+    fn tap(app: &mut App, key: KeyCode) {
+        press(app, key);
+        step(app, 1);
+        release(app, key);
+    }
+
+    fn landed_app() -> App {
+        let mut app = test_app(TEST_ROOM);
+        step(&mut app, 60);
+        app
+    }
+
+    fn move_input(app: &App, source: InputSource) -> Vec2 {
+        let world = app.world();
+        read_move_input(
+            source,
+            world.resource(),
+            world.resource(),
+            world.resource(),
+            None,
+        )
+    }
+
+    #[test]
+    fn diagonal_keys_are_not_faster() {
+        let mut app = test_app(TEST_ROOM);
+        press(&mut app, KeyCode::KeyD);
+        press(&mut app, KeyCode::ArrowUp);
+
+        let direction = move_input(&app, InputSource::Shared);
+        assert!((direction.length() - 1.0).abs() < 1e-5);
+        assert!(direction.x > 0.0 && direction.y > 0.0);
+    }
+
+    #[test]
+    fn keys_and_stick_together_are_clamped() {
+        let mut app = test_app(TEST_ROOM);
+        let gamepad = connect_gamepad(&mut app, 0);
+        step(&mut app, 1);
+
+        set_stick(&mut app, gamepad, Vec2::new(0.4, 0.0));
+        assert_eq!(move_input(&app, InputSource::Shared), Vec2::new(0.4, 0.0));
+
+        press(&mut app, KeyCode::KeyD);
+        set_stick(&mut app, gamepad, Vec2::new(0.8, 0.6));
+        let direction = move_input(&app, InputSource::Shared);
+        assert!((direction.length() - 1.0).abs() < 1e-5);
+
+        // Keyboard players ignore the stick.
+        assert_eq!(move_input(&app, InputSource::KeyboardB), Vec2::ZERO);
+    }
+
+    const LONG_FLOOR: &[&str] = &[
+        "##############",
+        "#............#",
+        "#............#",
+        "#P...........#",
+        "##############",
+    ];
+
+    /// Puts a solid block `height` pixels tall on the floor at column `col` and
+    /// returns its centre.
+    fn spawn_lip(app: &mut App, col: usize, height: f32) -> Vec2 {
+        let world = app.world_mut();
+        let level = world.resource::<LevelData>();
+        let config = *world.resource::<LevelConfig>();
+        let floor = level.tile_center(col, level.height() - 1, &config);
+        let center = floor + Vec2::new(0.0, (config.tile_size + height) * 0.5);
+        world.spawn((
+            TransformBundle::from_transform(Transform::from_translation(center.extend(0.0))),
+            RigidBody::Static,
+            Collider::rectangle(config.tile_size, height),
+            crate::SOLID_LAYERS,
+        ));
+        center
+    }
+
+    fn walk_right_into_lip(height: f32) -> (f32, Vec2) {
+        let mut app = test_app(LONG_FLOOR);
+        step(&mut app, 1);
+        let lip = spawn_lip(&mut app, 5, height);
+        step(&mut app, 30);
+
+        press(&mut app, KeyCode::KeyD);
+        step(&mut app, 90);
+        let x = single::<Transform, _>(&mut app, |transform| transform.translation.x);
+        (x, lip)
+    }
+
+    #[test]
+    fn walking_steps_up_onto_a_low_lip() {
+        let (x, lip) = walk_right_into_lip(6.0);
+        assert!(x > lip.x, "stopped at {x}, lip at {}", lip.x);
+    }
+
+    #[test]
+    fn a_half_tile_ledge_is_too_tall_to_step_onto() {
+        let (x, lip) = walk_right_into_lip(crate::DEFAULT_TILE_SIZE * 0.5);
+        assert!(x < lip.x, "climbed to {x}, ledge at {}", lip.x);
+    }
+
+    fn sides(app: &mut App) -> (bool, bool, bool, bool) {
+        single::<ContactSides, _>(app, |sides| {
+            (sides.left, sides.right, sides.ceiling, sides.floor)
+        })
+    }
+
+    #[test]
+    fn contact_sides_report_the_walls_being_pushed() {
+        let mut app = test_app(&["#####", "#...#", "#...#", "#P..#", "#####"]);
+        step(&mut app, 60);
+        assert_eq!(sides(&mut app), (false, false, false, true));
+
+        press(&mut app, KeyCode::KeyA);
+        step(&mut app, 30);
+        assert_eq!(sides(&mut app), (true, false, false, true));
+
+        release(&mut app, KeyCode::KeyA);
+        press(&mut app, KeyCode::KeyD);
+        step(&mut app, 30);
+        assert_eq!(sides(&mut app), (false, true, false, true));
+    }
+
+    #[test]
+    fn a_closed_door_is_floor_to_jump_from() {
+        let mut app = test_app(&["#######", "#.....#", "#..P..#", "#..D..#", "#######"]);
+        step(&mut app, 60);
+        let standing = single::<Position, _>(&mut app, |position| position.y);
+        assert!(single::<Grounded, _>(&mut app, |grounded| grounded.0));
+        assert_eq!(sides(&mut app), (false, false, false, true));
+
+        tap(&mut app, KeyCode::Space);
+        step(&mut app, 10);
+        let y = single::<Position, _>(&mut app, |position| position.y);
+        assert!(y > standing + 10.0, "rose from {standing} to {y}");
+    }
+
+    fn coop_offsets(map: &[&str]) -> Vec<f32> {
+        let mut app = test_app(map);
+        app.insert_resource(PlayerConfig {
+            coop: true,
+            ..default()
+        });
+        step(&mut app, 1);
+        let world = app.world_mut();
+        let mut offsets: Vec<f32> = world
+            .query::<&SpawnOffset>()
+            .iter(world)
+            .map(|offset| offset.0.x / crate::DEFAULT_TILE_SIZE)
+            .collect();
+        offsets.sort_by(f32::total_cmp);
+        offsets
+    }
+
+    #[test]
+    fn the_second_player_spawns_beside_the_first_but_never_in_a_wall() {
+        assert_eq!(coop_offsets(&["#####", "#.P.#", "#####"]), [0.0, 1.0]);
+        assert_eq!(coop_offsets(&["#####", "#.P##", "#####"]), [-1.0, 0.0]);
+        assert_eq!(coop_offsets(&["#####", "##PD#", "#####"]), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn contact_floor_follows_gravity() {
+        let mut app = test_app(&["#####", "#...#", "#P..#", "#####"]);
+        step(&mut app, 60);
+        tap(&mut app, KeyCode::KeyG);
+        step(&mut app, 60);
+
+        // Resting on the top row, which is the floor while gravity is flipped.
+        assert!(gravity_flipped(&app));
+        let (_, _, ceiling, floor) = sides(&mut app);
+        assert!(floor && !ceiling);
+        assert!(single::<Grounded, _>(&mut app, |grounded| grounded.0));
+    }
+
+    fn gravity_flipped(app: &App) -> bool {
+        app.world().resource::<GravityFlip>().0
+    }
+
+    #[test]
+    fn sprite_colour_is_the_tint_times_the_state_colour() {
+        let mut app = landed_app();
+        let tint = Color::srgb(0.5, 1.0, 0.25);
+        let world = app.world_mut();
+        world
+            .query_filtered::<&mut PlayerTint, With<Player>>()
+            .single_mut(world)
+            .0 = tint;
+        step(&mut app, 1);
+
+        let standing = app.world().resource::<AnimationConfig>().standing.tint;
+        let color = single::<Sprite, _>(&mut app, |sprite| sprite.color);
+        assert_eq!(color, multiply_colors(tint, standing));
+
+        tap(&mut app, KeyCode::ShiftLeft);
+        let dashing = app.world().resource::<DashTints>().horizontal;
+        let color = single::<Sprite, _>(&mut app, |sprite| sprite.color);
+        assert_ne!(dashing, Color::WHITE);
+        assert_eq!(color, multiply_colors(tint, dashing));
+    }
+
+    fn input_sources(app: &mut App) -> Vec<InputSource> {
+        let world = app.world_mut();
+        world
+            .query_filtered::<&InputSource, With<Player>>()
+            .iter(world)
+            .copied()
+            .collect()
+    }
+
+    #[test]
+    fn gamepad_hotplug_hands_player_two_over_and_back() {
+        let mut app = test_app(TEST_ROOM);
+        app.insert_resource(PlayerConfig {
+            coop: true,
+            ..default()
+        });
+        step(&mut app, 1);
+        assert!(input_sources(&mut app).contains(&InputSource::KeyboardB));
+
+        let gamepad = connect_gamepad(&mut app, 3);
+        step(&mut app, 1);
+        let sources = input_sources(&mut app);
+        assert!(sources.contains(&InputSource::Gamepad(gamepad)));
+        assert!(sources.contains(&InputSource::KeyboardA));
+
+        set_stick(&mut app, gamepad, Vec2::X);
+        disconnect_gamepad(&mut app, gamepad);
+        step(&mut app, 1);
+        let sources = input_sources(&mut app);
+        assert!(sources.contains(&InputSource::KeyboardB));
+        assert!(!sources.contains(&InputSource::Gamepad(gamepad)));
+    }
+
+    #[test]
+    fn unplugging_mid_move_leaves_no_stuck_stick() {
+        let mut app = landed_app();
+        let gamepad = connect_gamepad(&mut app, 0);
+        step(&mut app, 1);
+        set_stick(&mut app, gamepad, Vec2::X);
+        step(&mut app, 10);
+        assert!(single::<LinearVelocity, _>(&mut app, |velocity| velocity.x) > 0.0);
+
+        disconnect_gamepad(&mut app, gamepad);
+        step(&mut app, 1);
+        assert_eq!(move_input(&app, InputSource::Shared), Vec2::ZERO);
+        step(&mut app, 1);
+        assert_eq!(
+            single::<LinearVelocity, _>(&mut app, |velocity| velocity.x),
+            0.0
+        );
+    }
+
+    #[test]
+    fn run_playback_rate_follows_speed_within_bounds() {
+        let config = AnimationConfig::default();
+        let (min, max) = config.run_rate;
+        let move_speed = 360.0;
+        assert_eq!(config.run_playback_rate(move_speed, move_speed), 1.0);
+        assert_eq!(config.run_playback_rate(-180.0, move_speed), 0.5);
+        assert_eq!(config.run_playback_rate(1.0, move_speed), min);
+        assert_eq!(config.run_playback_rate(10_000.0, move_speed), max);
+    }
+
+    /// Frames until the running clip moves on from its first frame with the
+    /// stick held at `stick`.
+    fn frames_per_run_frame(stick: f32) -> usize {
+        let mut app = landed_app();
+        let gamepad = connect_gamepad(&mut app, 0);
+        step(&mut app, 1);
+        set_stick(&mut app, gamepad, Vec2::new(stick, 0.0));
+        step(&mut app, 1);
+
+        // Wait for the clip to start over so both runs count from the same point.
+        // Count shown atlas frames rather than clip positions, so a clip whose
+        // frames all look the same would fail.
+        let frame = |app: &mut App| single::<TextureAtlas, _>(app, |atlas| atlas.index);
+        let first = AnimationConfig::default().running.frames[0].0;
+        while frame(&mut app) != first {
+            step(&mut app, 1);
+        }
+        (1..200)
+            .find(|_| {
+                step(&mut app, 1);
+                frame(&mut app) != first
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn slower_running_holds_each_frame_longer() {
+        let full = frames_per_run_frame(1.0);
+        let half = frames_per_run_frame(0.5);
+        assert!(
+            half.abs_diff(full * 2) <= 2,
+            "{full} frames at full speed, {half} at half"
+        );
+    }
+
+    #[test]
+    fn garbage_timing_values_are_clamped_or_replaced() {
+        let file = "(dash_duration: 0.0, dash_cooldown: -3.0, coyote_time: NaN, \
+                    jump_buffer: inf, input_ramp: 1e9)";
+        let timing = ron::from_str::<TimingConfig>(file)
+            .unwrap()
+            .validate_and_clamp();
+        let defaults = TimingConfig::default();
+
+        assert_eq!(timing.dash_duration, 0.02);
+        assert_eq!(timing.dash_cooldown, 0.0);
+        assert_eq!(timing.coyote_time, defaults.coyote_time);
+        assert_eq!(timing.jump_buffer, defaults.jump_buffer);
+        assert_eq!(timing.input_ramp, 1.0);
+    }
+
+    #[test]
+    fn sane_timing_values_are_kept() {
+        let file = "(dash_duration: 0.25, dash_cooldown: 0.5, coyote_time: 0.1, \
+                    jump_buffer: 0.2, input_ramp: 0.0)";
+        let timing = ron::from_str::<TimingConfig>(file)
+            .unwrap()
+            .validate_and_clamp();
+        assert_eq!(
+            (
+                timing.dash_duration,
+                timing.dash_cooldown,
+                timing.coyote_time,
+                timing.jump_buffer,
+                timing.input_ramp
+            ),
+            (0.25, 0.5, 0.1, 0.2, 0.0)
+        );
+    }
+
+    fn dashing(app: &mut App) -> bool {
+        single::<PlayerState, _>(app, |state| *state == PlayerState::Dashing)
+    }
+
+    /// Frames from the dash press until the player stops dashing.
+    fn dash_frames(app: &mut App) -> usize {
+        (1..)
+            .find(|_| {
+                step(app, 1);
+                !dashing(app)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn a_burst_dash_lasts_dash_duration_then_cools_down() {
+        let mut app = landed_app();
+        let timing = TimingConfig::default();
+
+        tap(&mut app, KeyCode::ShiftLeft);
+        assert!(dashing(&mut app));
+        let frames = 1 + dash_frames(&mut app);
+        let expected = (timing.dash_duration * PHYSICS_HZ as f32).round() as usize;
+        assert!(frames.abs_diff(expected) <= 1, "dashed {frames} frames");
+
+        // Still cooling down: the press is ignored.
+        tap(&mut app, KeyCode::ShiftLeft);
+        assert!(!dashing(&mut app));
+
+        let cooldown = (timing.dash_cooldown * PHYSICS_HZ as f32).ceil() as usize;
+        step(&mut app, cooldown);
+        tap(&mut app, KeyCode::ShiftLeft);
+        assert!(dashing(&mut app));
+    }
+
+    fn sustained_app() -> App {
+        let mut app = test_app(TEST_ROOM);
+        app.insert_resource(PlayerConfig {
+            dash_mode: DashMode::Sustained,
+            ..default()
+        });
+        step(&mut app, 60);
+        app
+    }
+
+    #[test]
+    fn a_sustained_dash_lasts_while_held() {
+        let mut app = sustained_app();
+        press(&mut app, KeyCode::ShiftLeft);
+        step(&mut app, 30);
+        assert!(dashing(&mut app), "ended before the button was released");
+
+        release(&mut app, KeyCode::ShiftLeft);
+        step(&mut app, 1);
+        assert!(!dashing(&mut app));
+        assert!(single::<EnergyMeter, _>(&mut app, |energy| energy.0) < 1.0);
+    }
+
+    #[test]
+    fn a_sustained_dash_ends_when_energy_runs_out_and_refills() {
+        let mut app = sustained_app();
+        let config = PlayerConfig::default();
+        press(&mut app, KeyCode::ShiftLeft);
+        step(&mut app, 1);
+
+        let frames = 1 + dash_frames(&mut app);
+        let expected = (PHYSICS_HZ as f32 / config.dash_energy_drain).round() as usize;
+        assert!(frames.abs_diff(expected) <= 1, "dashed {frames} frames");
+        assert_eq!(single::<EnergyMeter, _>(&mut app, |energy| energy.0), 0.0);
+
+        release(&mut app, KeyCode::ShiftLeft);
+        step(&mut app, 30);
+        let energy = single::<EnergyMeter, _>(&mut app, |energy| energy.0);
+        assert!(energy > 0.0 && energy < 1.0, "energy {energy}");
+    }
+
+    #[test]
+    fn jumping_out_of_a_dash_keeps_more_than_running_speed() {
+        let mut app = landed_app();
+        tap(&mut app, KeyCode::ShiftLeft);
+        step(&mut app, 3);
+        assert_eq!(
+            single::<PlayerState, _>(&mut app, |state| *state),
+            PlayerState::Dashing
+        );
+
+        tap(&mut app, KeyCode::Space);
+        step(&mut app, 2);
+        assert_eq!(
+            single::<PlayerState, _>(&mut app, |state| *state),
+            PlayerState::Jumping
+        );
+        let speed = single::<LinearVelocity, _>(&mut app, |velocity| velocity.x);
+        assert!(speed > PlayerConfig::default().move_speed, "speed {speed}");
+    }
+
+    /// A conveyor-like shove on top of whatever the movement systems set.
+    fn shove_right(mut query: Query<&mut LinearVelocity, With<Player>>) {
+        for mut velocity in &mut query {
+            velocity.x += 2000.0;
+        }
+    }
+
+    fn shoved_app() -> App {
+        let mut app = landed_app();
+        app.add_systems(
+            Update,
+            shove_right
+                .after(step_up_ledges)
+                .before(cap_horizontal_speed),
+        );
+        app
+    }
+
+    #[test]
+    fn stacked_pushes_are_capped_at_max_horizontal_speed() {
+        let mut app = shoved_app();
+        press(&mut app, KeyCode::KeyD);
+        for _ in 0..10 {
+            step(&mut app, 1);
+            let speed = single::<LinearVelocity, _>(&mut app, |velocity| velocity.x);
+            assert!(
+                speed <= PlayerConfig::default().max_horizontal_speed,
+                "speed {speed}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_dash_is_capped_at_max_dash_speed() {
+        let mut app = shoved_app();
+        let config = PlayerConfig::default();
+        press(&mut app, KeyCode::KeyD);
+        tap(&mut app, KeyCode::ShiftLeft);
+        assert!(dashing(&mut app));
+        let speed = single::<LinearVelocity, _>(&mut app, |velocity| velocity.x);
+        assert!(
+            speed > config.max_horizontal_speed && speed <= config.max_dash_speed,
+            "speed {speed}"
+        );
+    }
+
+    #[test]
+    fn grounding_threshold_is_the_collider_half_height() {
+        let mut app = landed_app();
+
+        let world = app.world_mut();
+        let (player, collider) = world
+            .query_filtered::<(&Transform, &Collider), With<Player>>()
+            .single(world);
+        let player = player.translation;
+        let half_extents = collider.shape_scaled().as_cuboid().unwrap().half_extents;
+        assert_eq!(half_extents.y, PLAYER_HALF_HEIGHT);
+
+        let floor = world
+            .query_filtered::<&ColliderAabb, With<TileCollider>>()
+            .iter(world)
+            .filter(|tile| tile.min.x <= player.x && tile.max.x >= player.x)
+            .map(|tile| tile.max.y)
+            .filter(|&top| top <= player.y)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let feet = player.y - PLAYER_HALF_HEIGHT;
+        assert!(
+            (feet - floor).abs() <= GROUND_TOLERANCE,
+            "feet {feet}, floor {floor}"
+        );
+        assert!(single::<ContactSides, _>(&mut app, |sides| sides.floor));
+    }
+
+    fn grounding_recomputes(app: &mut App) -> u32 {
+        single::<GroundingCache, _>(app, |cache| cache.recomputes)
+    }
+
+    #[test]
+    fn grounding_cache_is_hit_while_standing_still() {
+        let mut app = landed_app();
+        assert!(single::<Grounded, _>(&mut app, |grounded| grounded.0));
+
+        let before = grounding_recomputes(&mut app);
+        step(&mut app, 60);
+        assert_eq!(grounding_recomputes(&mut app), before);
+    }
+
+    #[test]
+    fn grounding_cache_is_invalidated_by_a_vertical_move() {
+        let mut app = landed_app();
+        let before = grounding_recomputes(&mut app);
+
+        let world = app.world_mut();
+        let mut players = world.query_filtered::<&mut Position, With<Player>>();
+        players.single_mut(world).y += 10.0;
+        // Grounding reads the transform physics writes back at the end of the frame.
+        step(&mut app, 2);
+
+        assert!(grounding_recomputes(&mut app) > before);
+    }
+
+    #[test]
+    fn grounding_cache_ignores_solver_jitter() {
+        let cache = GroundingCache {
+            y: 10.0,
+            velocity_y: 0.3,
+            recomputes: 0,
+        };
+        assert!(!cache.is_dirty(10.001, 0.0));
+        assert!(cache.is_dirty(10.5, 0.3));
+        assert!(cache.is_dirty(10.0, -25.0));
+    }
+
+    #[test]
+    fn idle_breathing_leaves_the_collider_scale_alone() {
+        let mut app = landed_app();
+
+        let mut breathed = false;
+        for _ in 0..180 {
+            step(&mut app, 1);
+            assert_eq!(single::<Transform, _>(&mut app, |t| t.scale), Vec3::ONE);
+            breathed |=
+                single::<Sprite, _>(&mut app, |sprite| sprite.custom_size != Some(PLAYER_SIZE));
+        }
+        assert!(breathed, "the idle player never breathed");
+    }
+}
@@ -0,0 +1,162 @@
+//! Headless app for tests: the level and player plugins on real physics, with
+//! no window or renderer, and a clock that advances by exactly one physics
+//! step per update so runs are repeatable.
+
+use bevy::input::gamepad::{
+    gamepad_connection_system, GamepadConnection, GamepadConnectionEvent, GamepadInfo,
+};
+use bevy::prelude::*;
+use bevy::time::TimeUpdateStrategy;
+use bevy_xpbd_2d::prelude::*;
+use std::time::Duration;
+
+use crate::level::{LevelOverride, LevelPlugin};
+use crate::player::{PlayerPlugin, TimingConfig};
+use crate::{GRAVITY, PHYSICS_HZ};
+
+/// A small walled room with a floor, the spawn near its bottom left.
+pub(crate) const TEST_ROOM: &[&str] = &[
+    "####################",
+    "#..................#",
+    "#..................#",
+    "#..................#",
+    "#P.................#",
+    "####################",
+];
+
+/// Builds an app that plays `map` headlessly. Nothing has run yet; call
+/// [`step`] to run startup and then one frame per call.
+///
+/// Input comes from [`ButtonInput<KeyCode>`] directly, without the input
+/// plugin, so keys pressed with [`press`] stay "just pressed" for exactly one
+/// [`step`], like a real key press. Gamepads only connect through
+/// [`connect_gamepad`].
+pub(crate) fn test_app(map: &[&str]) -> App {
+    let mut app = App::new();
+    app.add_plugins((
+        MinimalPlugins,
+        TransformPlugin,
+        HierarchyPlugin,
+        AssetPlugin::default(),
+    ))
+    .init_asset::<Image>()
+    .init_asset::<TextureAtlasLayout>()
+    .init_resource::<ButtonInput<KeyCode>>()
+    .init_resource::<ButtonInput<GamepadButton>>()
+    .init_resource::<Axis<GamepadAxis>>()
+    .init_resource::<Axis<GamepadButton>>()
+    .init_resource::<Gamepads>()
+    .add_event::<GamepadConnectionEvent>()
+    .add_systems(PreUpdate, gamepad_connection_system)
+    .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_secs_f64(
+        1.0 / PHYSICS_HZ,
+    )))
+    .insert_resource(Time::new_with(Physics::fixed_once_hz(PHYSICS_HZ)))
+    .insert_resource(Gravity(Vec2::NEG_Y * GRAVITY))
+    .insert_resource(LevelOverride(
+        map.iter().map(|row| row.to_string()).collect(),
+    ))
+    .add_plugins((PhysicsPlugins::default(), LevelPlugin, PlayerPlugin))
+    // Don't let a timing.ron in the user's data directory change the results.
+    .insert_resource(TimingConfig::default());
+    app
+}
+
+/// Runs `frames` updates, each one physics step long.
+pub(crate) fn step(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.update();
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .clear();
+    }
+}
+
+/// Holds `key` down from the next update; it reads as just pressed once.
+pub(crate) fn press(app: &mut App, key: KeyCode) {
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .press(key);
+}
+
+pub(crate) fn release(app: &mut App, key: KeyCode) {
+    app.world_mut()
+        .resource_mut::<ButtonInput<KeyCode>>()
+        .release(key);
+}
+
+/// Plugs in gamepad `id`; it is registered on the next [`step`].
+pub(crate) fn connect_gamepad(app: &mut App, id: usize) -> Gamepad {
+    let gamepad = Gamepad::new(id);
+    app.world_mut().send_event(GamepadConnectionEvent::new(
+        gamepad,
+        GamepadConnection::Connected(GamepadInfo {
+            name: "test gamepad".to_string(),
+        }),
+    ));
+    gamepad
+}
+
+pub(crate) fn disconnect_gamepad(app: &mut App, gamepad: Gamepad) {
+    app.world_mut().send_event(GamepadConnectionEvent::new(
+        gamepad,
+        GamepadConnection::Disconnected,
+    ));
+}
+
+/// Sets the left stick of `gamepad`, which stays put until set again.
+pub(crate) fn set_stick(app: &mut App, gamepad: Gamepad, value: Vec2) {
+    let mut axes = app.world_mut().resource_mut::<Axis<GamepadAxis>>();
+    axes.set(
+        GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX),
+        value.x,
+    );
+    axes.set(
+        GamepadAxis::new(gamepad, GamepadAxisType::LeftStickY),
+        value.y,
+    );
+}
+
+/// The only entity with component `T`, read through `f`.
+pub(crate) fn single<T: Component, R>(app: &mut App, f: impl FnOnce(&T) -> R) -> R {
+    let mut query = app
+        .world_mut()
+        .query_filtered::<&T, With<crate::player::Player>>();
+    f(query.single(app.world()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player::{Grounded, PlayerState};
+
+    #[test]
+    fn player_spawns_and_lands_on_the_floor() {
+        let mut app = test_app(TEST_ROOM);
+        step(&mut app, 90);
+
+        assert!(single::<Grounded, _>(&mut app, |grounded| grounded.0));
+        assert_eq!(
+            single::<PlayerState, _>(&mut app, |state| *state),
+            PlayerState::Standing
+        );
+        assert!(single::<LinearVelocity, _>(&mut app, |velocity| velocity
+            .y
+            .abs()
+            < 1.0));
+    }
+
+    #[test]
+    fn held_key_moves_the_player() {
+        let mut app = test_app(TEST_ROOM);
+        step(&mut app, 60);
+        let start = single::<Transform, _>(&mut app, |transform| transform.translation.x);
+
+        press(&mut app, KeyCode::KeyD);
+        step(&mut app, 30);
+        release(&mut app, KeyCode::KeyD);
+
+        let end = single::<Transform, _>(&mut app, |transform| transform.translation.x);
+        assert!(end > start + 50.0, "moved from {start} to {end}");
+    }
+}